@@ -0,0 +1,73 @@
+//! Compare the evaluation of an expression tree with the evaluation of the
+//! same expression after it has been compiled or lowered to a program, and
+//! with the evaluation of all the rows at once.
+//!
+//! Run with `cargo bench`.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use arithmetic_parser::{ArithmeticExpression, Machine};
+
+const ITERATIONS: usize = 1_000_000;
+
+const FORMULAS: [&str; 3] = [
+    "(x+y)/(x-y)",
+    "3 * x^2 + 2 * x * y - sqrt(abs(y)) + 1",
+    "max(sin x, cos y, 0.5) * exp(-(x^2 + y^2) / 2) + log(2, 1 + abs x)",
+];
+
+/// Run `f` for every iteration and print the average time per call.
+fn measure<F: FnMut(f64) -> f64>(name: &str, mut f: F) {
+    let start = Instant::now();
+    let mut sum = 0_f64;
+    for i in 0..ITERATIONS {
+        sum += f(i as f64 * 1e-6);
+    }
+    let elapsed = start.elapsed();
+    black_box(sum);
+    println!(
+        "  {:<10} {:>8.1} ns/iter",
+        name,
+        elapsed.as_nanos() as f64 / ITERATIONS as f64
+    );
+}
+
+fn main() {
+    for formula in FORMULAS.iter() {
+        println!("{}", formula);
+        let expression = ArithmeticExpression::parse(formula).unwrap();
+
+        // The variables are passed like the values of the other cases,
+        // without building a map in the timed loop
+        measure("evaluate", |x| {
+            let variables = [("x", x), ("y", 2_f64)];
+            black_box(&expression).evaluate(&variables).unwrap()
+        });
+
+        let compiled = expression.compile(&["x", "y"]).unwrap();
+        measure("compiled", |x| {
+            black_box(&compiled).evaluate(&[x, 2_f64]).unwrap()
+        });
+
+        let program = expression.to_program(&["x", "y"]).unwrap();
+        let mut machine = Machine::new();
+        measure("program", |x| {
+            machine.run(black_box(&program), &[x, 2_f64]).unwrap()
+        });
+
+        let x: Vec<f64> = (0..ITERATIONS).map(|i| i as f64 * 1e-6).collect();
+        let y = vec![2_f64; ITERATIONS];
+        let columns = [("x", &x[..]), ("y", &y[..])].iter().cloned().collect();
+        let mut out = vec![0_f64; ITERATIONS];
+        let start = Instant::now();
+        expression.evaluate_batch(&columns, &mut out).unwrap();
+        let elapsed = start.elapsed();
+        black_box(&out);
+        println!(
+            "  {:<10} {:>8.1} ns/iter",
+            "batch",
+            elapsed.as_nanos() as f64 / ITERATIONS as f64
+        );
+    }
+}
@@ -0,0 +1,526 @@
+use std::collections::HashMap;
+use std::mem;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::data_structures::{ArithmeticExpression, Operator};
+use super::error::EvalError;
+use super::options::{ArithmeticMode, EvaluationOptions, ShadowingPolicy};
+
+/// The values of an expression for every row.
+enum Column<'a> {
+    /// The same value for every row, e.g., a number.
+    Scalar(f64),
+    /// The values of a variable.
+    Slice(&'a [f64]),
+    /// The values computed by an operator.
+    Vector(Vec<f64>),
+}
+
+impl Column<'_> {
+    fn at(&self, row: usize) -> f64 {
+        match self {
+            Column::Scalar(value) => *value,
+            Column::Slice(values) => values[row],
+            Column::Vector(values) => values[row],
+        }
+    }
+}
+
+/// An operand of an operator applied to a whole column, which is either
+/// an evaluated column or the column where the result is written, if the
+/// result overwrites the operand.
+#[derive(Clone, Copy)]
+enum Operand<'b> {
+    Scalar(f64),
+    Slice(&'b [f64]),
+    Destination,
+}
+
+/// The number of rows evaluated by each task of a parallel evaluation.
+#[cfg(feature = "parallel")]
+const PARALLEL_CHUNK_ROWS: usize = 4096;
+
+impl ArithmeticExpression {
+    /// Evaluate the expression for many rows at once, with the default
+    /// [`EvaluationOptions`]. `columns` contains the values of each variable
+    /// for every row, and the result of row `i` is written to `out[i]`.
+    ///
+    /// The tree is walked once, and each operator is applied to whole
+    /// columns, so that the loops over the rows can be vectorized. The
+    /// results are the same as evaluating the expression row by row with
+    /// [`ArithmeticExpression::evaluate`].
+    ///
+    /// Numbers and constants are not expanded to columns, and the results of
+    /// the operators are written over the columns of their operands when
+    /// possible, so that only a few columns are allocated.
+    ///
+    /// ## Errors:
+    /// [`EvalError::ColumnLength`] is returned if a column does not have
+    /// the same length as `out`. Otherwise, an error is returned if the
+    /// evaluation of a row fails; if several rows fail, the error is not
+    /// necessarily the one of the first row. `out` can be partially
+    /// modified if an error is returned.
+    ///
+    /// ## Example:
+    /// ```
+    /// use arithmetic_parser::ArithmeticExpression;
+    /// let expression = ArithmeticExpression::parse("(x+y)/(x-y)").unwrap();
+    /// let x = [5_f64, 2_f64, 3_f64];
+    /// let y = [1_f64, 1_f64, 1_f64];
+    /// let columns = [("x", &x[..]), ("y", &y[..])].iter().cloned().collect();
+    /// let mut out = [0_f64; 3];
+    /// expression.evaluate_batch(&columns, &mut out).unwrap();
+    /// assert_eq!([1.5, 3_f64, 2_f64], out);
+    /// ```
+    pub fn evaluate_batch(
+        &self,
+        columns: &HashMap<&str, &[f64]>,
+        out: &mut [f64],
+    ) -> Result<(), EvalError> {
+        self.evaluate_batch_with(columns, out, &EvaluationOptions::default())
+    }
+
+    /// Evaluate the expression for many rows like
+    /// [`ArithmeticExpression::evaluate_batch`], with custom options.
+    pub fn evaluate_batch_with(
+        &self,
+        columns: &HashMap<&str, &[f64]>,
+        out: &mut [f64],
+        options: &EvaluationOptions,
+    ) -> Result<(), EvalError> {
+        check_lengths(columns, out.len())?;
+        evaluate_into(self, columns, out, options).map_err(|error| {
+            self.report_missing_variables(error, |name| columns.contains_key(name))
+        })
+    }
+
+    /// Evaluate the expression for many rows like
+    /// [`ArithmeticExpression::evaluate_batch`], splitting the rows among
+    /// the threads of the global [rayon](https://docs.rs/rayon) pool.
+    ///
+    /// The results are identical to the ones of the sequential evaluation.
+    /// Requires the `parallel` feature.
+    ///
+    /// ## Errors:
+    /// The same as [`ArithmeticExpression::evaluate_batch`]. If rows in
+    /// different chunks fail, the error of the first chunk is returned.
+    #[cfg(feature = "parallel")]
+    pub fn evaluate_batch_parallel(
+        &self,
+        columns: &HashMap<&str, &[f64]>,
+        out: &mut [f64],
+    ) -> Result<(), EvalError> {
+        self.evaluate_batch_parallel_with(columns, out, &EvaluationOptions::default())
+    }
+
+    /// Evaluate the expression for many rows in parallel like
+    /// [`ArithmeticExpression::evaluate_batch_parallel`], with custom
+    /// options.
+    #[cfg(feature = "parallel")]
+    pub fn evaluate_batch_parallel_with(
+        &self,
+        columns: &HashMap<&str, &[f64]>,
+        out: &mut [f64],
+        options: &EvaluationOptions,
+    ) -> Result<(), EvalError> {
+        check_lengths(columns, out.len())?;
+        // The errors which do not depend on the rows (e.g., a missing
+        // variable) are found once, without rows, so that they are the
+        // same as the sequential ones even when there are no chunks.
+        let no_rows = columns.keys().map(|name| (*name, &[][..])).collect();
+        self.evaluate_batch_with(&no_rows, &mut [], options)?;
+        let results: Vec<Result<(), EvalError>> = out
+            .par_chunks_mut(PARALLEL_CHUNK_ROWS)
+            .enumerate()
+            .map(|(i, out_chunk)| {
+                let start = i * PARALLEL_CHUNK_ROWS;
+                let end = start + out_chunk.len();
+                let chunk_columns = columns
+                    .iter()
+                    .map(|(name, column)| (*name, &column[start..end]))
+                    .collect();
+                self.evaluate_batch_with(&chunk_columns, out_chunk, options)
+            })
+            .collect();
+        results.into_iter().collect()
+    }
+}
+
+fn check_lengths(columns: &HashMap<&str, &[f64]>, rows: usize) -> Result<(), EvalError> {
+    for (name, column) in columns {
+        if column.len() != rows {
+            return Err(EvalError::ColumnLength {
+                name: name.to_string(),
+                expected: rows,
+                found: column.len(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Evaluate the expression, writing the result to `out`.
+fn evaluate_into(
+    expression: &ArithmeticExpression,
+    columns: &HashMap<&str, &[f64]>,
+    out: &mut [f64],
+    options: &EvaluationOptions,
+) -> Result<(), EvalError> {
+    match expression {
+        ArithmeticExpression::Node { node, operands } => {
+            let operands = evaluate_operands(node, operands, columns, out.len(), options)?;
+            apply_column(node, &operands, None, out)?;
+            if options.arithmetic == ArithmeticMode::Checked {
+                check_column(node, &operands, out)?;
+            }
+        }
+        leaf => match evaluate_column(leaf, columns, out.len(), options)? {
+            Column::Scalar(value) => out.iter_mut().for_each(|x| *x = value),
+            Column::Slice(values) => out.copy_from_slice(values),
+            Column::Vector(values) => out.copy_from_slice(&values),
+        },
+    }
+    Ok(())
+}
+
+fn evaluate_column<'a>(
+    expression: &ArithmeticExpression,
+    columns: &HashMap<&str, &'a [f64]>,
+    rows: usize,
+    options: &EvaluationOptions,
+) -> Result<Column<'a>, EvalError> {
+    match expression {
+        ArithmeticExpression::NumberLeaf(n) => Ok(Column::Scalar(*n)),
+        ArithmeticExpression::VariableLeaf(x) => match columns.get(x.as_str()) {
+            Some(column) => Ok(Column::Slice(column)),
+            None => Err(EvalError::MissingVariables {
+                names: vec![x.clone()],
+            }),
+        },
+        ArithmeticExpression::Constant { name, value } => {
+            match (columns.get(name.as_str()), options.shadowing) {
+                (None, _) | (Some(_), ShadowingPolicy::PreferConstant) => {
+                    Ok(Column::Scalar(*value))
+                }
+                (Some(column), ShadowingPolicy::PreferVariable) => Ok(Column::Slice(column)),
+                (Some(_), ShadowingPolicy::Error) => {
+                    Err(EvalError::ShadowedConstant { name: name.clone() })
+                }
+            }
+        }
+        ArithmeticExpression::Node { node, operands } => {
+            let mut operands = evaluate_operands(node, operands, columns, rows, options)?;
+            // Without rows, the operator is never applied, like when the
+            // operands are columns
+            if rows > 0 && operands.iter().all(|x| matches!(x, Column::Scalar(_))) {
+                let args: Vec<f64> = operands.iter().map(|x| x.at(0)).collect();
+                let result = node.apply(&args)?;
+                return Ok(Column::Scalar(
+                    options.arithmetic.check(node, &args, result)?,
+                ));
+            }
+            // The checks need the operands, so they are not overwritten
+            let in_place = match options.arithmetic {
+                ArithmeticMode::Ieee => {
+                    operands.iter().position(|x| matches!(x, Column::Vector(_)))
+                }
+                ArithmeticMode::Checked => None,
+            };
+            let mut result = match in_place {
+                Some(i) => match mem::replace(&mut operands[i], Column::Scalar(0.0)) {
+                    Column::Vector(values) => values,
+                    _ => unreachable!(),
+                },
+                None => vec![0_f64; rows],
+            };
+            apply_column(node, &operands, in_place, &mut result)?;
+            if options.arithmetic == ArithmeticMode::Checked {
+                check_column(node, &operands, &result)?;
+            }
+            Ok(Column::Vector(result))
+        }
+    }
+}
+
+fn evaluate_operands<'a>(
+    node: &Operator,
+    operands: &[ArithmeticExpression],
+    columns: &HashMap<&str, &'a [f64]>,
+    rows: usize,
+    options: &EvaluationOptions,
+) -> Result<Vec<Column<'a>>, EvalError> {
+    if !node.is_nary(operands.len()) {
+        return Err(EvalError::WrongArity {
+            operator: node.clone(),
+            arguments: operands.len(),
+        });
+    }
+    operands
+        .iter()
+        .map(|operand| evaluate_column(operand, columns, rows, options))
+        .collect()
+}
+
+/// Apply an operator to every row, writing the result to `out`. The
+/// operand at position `in_place`, if any, is already in `out`. The most
+/// common operators have their own loop, so that it can be vectorized.
+fn apply_column(
+    operator: &Operator,
+    operands: &[Column<'_>],
+    in_place: Option<usize>,
+    out: &mut [f64],
+) -> Result<(), EvalError> {
+    let operands: Vec<Operand<'_>> = operands
+        .iter()
+        .enumerate()
+        .map(|(i, operand)| match operand {
+            _ if in_place == Some(i) => Operand::Destination,
+            Column::Scalar(value) => Operand::Scalar(*value),
+            Column::Slice(values) => Operand::Slice(values),
+            Column::Vector(values) => Operand::Slice(values),
+        })
+        .collect();
+    match (operator, operands.as_slice()) {
+        (Operator::Plus, [x, y]) => map_binary(*x, *y, out, |x, y| x + y),
+        (Operator::Minus, [x, y]) => map_binary(*x, *y, out, |x, y| x - y),
+        (Operator::Star, [x, y]) => map_binary(*x, *y, out, |x, y| x * y),
+        (Operator::Slash, [x, y]) => map_binary(*x, *y, out, |x, y| x / y),
+        (Operator::Caret, [x, y]) | (Operator::Pow, [x, y]) => map_binary(*x, *y, out, f64::powf),
+        (Operator::Minus, [x]) => map_unary(*x, out, |x| -x),
+        (Operator::Sqrt, [x]) => map_unary(*x, out, f64::sqrt),
+        (Operator::Abs, [x]) => map_unary(*x, out, f64::abs),
+        (Operator::Floor, [x]) => map_unary(*x, out, f64::floor),
+        (Operator::Ceil, [x]) => map_unary(*x, out, f64::ceil),
+        (Operator::Trunc, [x]) => map_unary(*x, out, f64::trunc),
+        _ => {
+            let mut args = Vec::with_capacity(operands.len());
+            for row in 0..out.len() {
+                args.clear();
+                args.extend(operands.iter().map(|operand| match operand {
+                    Operand::Scalar(value) => *value,
+                    Operand::Slice(values) => values[row],
+                    Operand::Destination => out[row],
+                }));
+                out[row] = operator.apply(&args)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn map_unary<F: Fn(f64) -> f64>(x: Operand<'_>, out: &mut [f64], f: F) {
+    match x {
+        Operand::Scalar(x) => out.iter_mut().for_each(|v| *v = f(x)),
+        Operand::Slice(x) => out.iter_mut().zip(x).for_each(|(v, x)| *v = f(*x)),
+        Operand::Destination => out.iter_mut().for_each(|v| *v = f(*v)),
+    }
+}
+
+fn map_binary<F: Fn(f64, f64) -> f64>(x: Operand<'_>, y: Operand<'_>, out: &mut [f64], f: F) {
+    use Operand::*;
+    match (x, y) {
+        (Scalar(x), Scalar(y)) => out.iter_mut().for_each(|v| *v = f(x, y)),
+        (Scalar(x), Slice(y)) => out.iter_mut().zip(y).for_each(|(v, y)| *v = f(x, *y)),
+        (Slice(x), Scalar(y)) => out.iter_mut().zip(x).for_each(|(v, x)| *v = f(*x, y)),
+        (Slice(x), Slice(y)) => out
+            .iter_mut()
+            .zip(x.iter().zip(y))
+            .for_each(|(v, (x, y))| *v = f(*x, *y)),
+        (Destination, Scalar(y)) => out.iter_mut().for_each(|v| *v = f(*v, y)),
+        (Destination, Slice(y)) => out.iter_mut().zip(y).for_each(|(v, y)| *v = f(*v, *y)),
+        (Scalar(x), Destination) => out.iter_mut().for_each(|v| *v = f(x, *v)),
+        (Slice(x), Destination) => out.iter_mut().zip(x).for_each(|(v, x)| *v = f(*x, *v)),
+        (Destination, Destination) => unreachable!("only one operand is overwritten"),
+    }
+}
+
+/// Check the result of an operator like [`ArithmeticMode::Checked`] does
+/// for a single row.
+fn check_column(
+    operator: &Operator,
+    operands: &[Column<'_>],
+    result: &[f64],
+) -> Result<(), EvalError> {
+    if let Some(row) = result.iter().position(|value| !value.is_finite()) {
+        let args: Vec<f64> = operands.iter().map(|operand| operand.at(row)).collect();
+        ArithmeticMode::Checked.check(operator, &args, result[row])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arity, Context};
+
+    #[test]
+    fn test_evaluate_batch() {
+        let inputs = [
+            "3",
+            "x",
+            "pi * x - y",
+            "(x + 3) * 4 + (4 + y) / x ^ 2",
+            "-x^2 + sqrt(abs y) - floor x + ceil y * trunc(-x)",
+            "+(x, y, 1) - max(1, x, y) + min(y) * pow(x, y)",
+            "atan2(y, x) + log(2, y) - hypot(x, y) + sign(x) * round(y)",
+            "1 / (x - x) + sqrt(-y) - ln x",
+            "2 * 3 + sqrt(2) * x",
+            "(x * y) - 1 + (1 - x * y) * -(x + y) / sqrt(x * y)",
+            "max(x * y, 2, y) + hypot(2, 3) - min(sqrt(x), y^2)",
+        ];
+        let x = [1.5_f64, -3_f64, 0_f64, 1e300, -0_f64];
+        let y = [4_f64, 0.25, 1e-300, f64::NAN, 2_f64];
+        let columns = [("x", &x[..]), ("y", &y[..])].iter().cloned().collect();
+        for input in inputs.iter() {
+            let expression = ArithmeticExpression::parse(input).unwrap();
+            let mut out = [0_f64; 5];
+            expression.evaluate_batch(&columns, &mut out).unwrap();
+            for row in 0..x.len() {
+                let variables = [("x", x[row]), ("y", y[row])];
+                let expected = expression.evaluate(&variables).unwrap();
+                assert_eq!(expected.to_bits(), out[row].to_bits(), "{}", input);
+            }
+        }
+
+        let expression = ArithmeticExpression::parse("x + 1").unwrap();
+        let mut out = [];
+        let columns = [("x", &[][..])].iter().cloned().collect();
+        expression.evaluate_batch(&columns, &mut out).unwrap();
+    }
+
+    #[test]
+    fn test_evaluate_batch_errors() {
+        let expression = ArithmeticExpression::parse("x + y").unwrap();
+        let x = [1_f64, 2_f64, 3_f64];
+        let y = [1_f64, 2_f64];
+        let mut out = [0_f64; 3];
+
+        let columns = [("x", &x[..]), ("y", &y[..])].iter().cloned().collect();
+        assert_eq!(
+            expression.evaluate_batch(&columns, &mut out),
+            Err(EvalError::ColumnLength {
+                name: "y".to_string(),
+                expected: 3,
+                found: 2
+            })
+        );
+
+        let columns = [("x", &x[..])].iter().cloned().collect();
+        assert_eq!(
+            expression.evaluate_batch(&columns, &mut out),
+            Err(EvalError::MissingVariables {
+                names: vec!["y".to_string()]
+            })
+        );
+        assert_eq!(out, [0_f64; 3]);
+
+        let options = EvaluationOptions {
+            arithmetic: ArithmeticMode::Checked,
+            ..EvaluationOptions::default()
+        };
+        let expression = ArithmeticExpression::parse("sqrt(x - 2)").unwrap();
+        assert_eq!(
+            expression.evaluate_batch_with(&columns, &mut out, &options),
+            Err(EvalError::DomainError {
+                operator: Operator::Sqrt
+            })
+        );
+
+        let mut context = Context::new();
+        context.register_function("f", Arity::Exactly(1), |args: &[f64]| {
+            if args[0] > 2.0 {
+                Err("too large")
+            } else {
+                Ok(args[0] * 2.0)
+            }
+        });
+        let expression = ArithmeticExpression::parse_with_context("f x", &context).unwrap();
+        assert_eq!(
+            expression.evaluate_batch(&columns, &mut out),
+            Err(EvalError::FunctionFailed {
+                name: "f".to_string(),
+                message: "too large".to_string()
+            })
+        );
+        let mut out = [0_f64; 2];
+        let columns = [("x", &y[..])].iter().cloned().collect();
+        expression.evaluate_batch(&columns, &mut out).unwrap();
+        assert_eq!(out, [2_f64, 4_f64]);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_evaluate_batch_parallel() {
+        let rows = PARALLEL_CHUNK_ROWS * 3 + 17;
+        let x: Vec<f64> = (0..rows).map(|i| (i as f64 - 5000.0) * 0.37).collect();
+        let y: Vec<f64> = (0..rows).map(|i| (i as f64).sin() * 1e3).collect();
+        let columns = [("x", &x[..]), ("y", &y[..])].iter().cloned().collect();
+
+        let expression =
+            ArithmeticExpression::parse("sqrt(x) * y^2 - atan2(y, x) + max(x, y, pi) / (x - y)")
+                .unwrap();
+        let mut sequential = vec![0_f64; rows];
+        let mut parallel = vec![0_f64; rows];
+        expression
+            .evaluate_batch(&columns, &mut sequential)
+            .unwrap();
+        expression
+            .evaluate_batch_parallel(&columns, &mut parallel)
+            .unwrap();
+        for row in 0..rows {
+            assert_eq!(sequential[row].to_bits(), parallel[row].to_bits());
+        }
+
+        let mut context = Context::new();
+        context.register_function("f", Arity::Exactly(1), |args: &[f64]| {
+            if args[0] > 0.0 {
+                Err(format!("{} is positive", args[0]))
+            } else {
+                Ok(args[0])
+            }
+        });
+        let expression = ArithmeticExpression::parse_with_context("f(x)", &context).unwrap();
+        assert_eq!(
+            expression.evaluate_batch_parallel(&columns, &mut parallel),
+            expression.evaluate_batch(&columns, &mut sequential)
+        );
+        let error = expression.evaluate_batch_parallel(&columns, &mut parallel[1..]);
+        assert!(
+            matches!(
+                error,
+                Err(EvalError::ColumnLength { expected, found, .. })
+                    if expected == rows - 1 && found == rows
+            ),
+            "{:?}",
+            error
+        );
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_evaluate_batch_parallel_no_rows() {
+        let options = EvaluationOptions {
+            shadowing: ShadowingPolicy::Error,
+            ..EvaluationOptions::default()
+        };
+        let expressions = [
+            ArithmeticExpression::parse("x + y").unwrap(),
+            ArithmeticExpression::parse("pi * x").unwrap(),
+            ArithmeticExpression::Node {
+                node: Operator::Sqrt,
+                operands: vec![],
+            },
+        ];
+        let columns = [("x", &[][..]), ("pi", &[][..])].iter().cloned().collect();
+        for expression in expressions.iter() {
+            let sequential = expression.evaluate_batch_with(&columns, &mut [], &options);
+            assert!(sequential.is_err(), "{:?}", expression);
+            assert_eq!(
+                expression.evaluate_batch_parallel_with(&columns, &mut [], &options),
+                sequential
+            );
+        }
+    }
+}
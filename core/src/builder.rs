@@ -0,0 +1,283 @@
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use super::context::is_valid_name;
+use super::data_structures::{ArithmeticExpression, Operator};
+use super::parser::DEFAULT_CONTEXT;
+
+/// An arithmetic expression built in code.
+///
+/// Expressions can be combined with the arithmetic operators of Rust, by
+/// value or by reference, and with methods named after the functions of the
+/// parser (e.g., [`Expr::sqrt`]). They convert to and from
+/// [`ArithmeticExpression`] with `From` and `Into`, and the trees are the
+/// same as the ones produced by [`ArithmeticExpression::parse`] for the
+/// corresponding input: e.g., `x + y + z` is `(x + y) + z`, `-x` is a node
+/// with a single operand and `pi` is a constant. To keep this property, the
+/// constructors panic on names and numbers of operands which the parser
+/// does not accept.
+///
+/// ## Example:
+/// ```
+/// use arithmetic_parser::{ArithmeticExpression, Expr};
+/// let (x, y) = (Expr::var("x"), Expr::var("y"));
+/// let expression: ArithmeticExpression = ((&x + &y) / (x - y) * 2.0).into();
+/// assert_eq!(expression, ArithmeticExpression::parse("(x+y)/(x-y) * 2").unwrap());
+///
+/// let expression = -Expr::var("r").pow(2.0) * Expr::var("pi");
+/// assert_eq!(
+///     ArithmeticExpression::from(expression),
+///     ArithmeticExpression::parse("-r^2 * pi").unwrap()
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Expr(ArithmeticExpression);
+
+impl Expr {
+    /// The variable `name`, or the constant `name` if it is one of the
+    /// constants known to [`ArithmeticExpression::parse`] (e.g., `pi`).
+    ///
+    /// ## Panics:
+    /// If `name` is not a valid variable name: it must not be empty, a
+    /// number or a built-in operator, and it must not contain spaces,
+    /// parenthesis, commas or symbolic operators.
+    pub fn var(name: &str) -> Expr {
+        assert!(is_valid_name(name), "Invalid variable name: {:?}", name);
+        Expr(match DEFAULT_CONTEXT.get_constant(name) {
+            Some(value) => ArithmeticExpression::Constant {
+                name: name.to_string(),
+                value,
+            },
+            None => ArithmeticExpression::VariableLeaf(name.to_string()),
+        })
+    }
+
+    /// The number `n`. Like the parser, a negative number is the negation
+    /// of a positive one (`Expr::num(-2.0)` is `-Expr::num(2.0)`) and NaN
+    /// is the constant `nan`.
+    pub fn num(n: f64) -> Expr {
+        if n.is_nan() {
+            Expr::var("nan")
+        } else if n.is_sign_negative() {
+            -Expr(ArithmeticExpression::NumberLeaf(-n))
+        } else {
+            Expr(ArithmeticExpression::NumberLeaf(n))
+        }
+    }
+
+    /// `operator` called with `operands`, such as `max(a, b, c)`.
+    ///
+    /// ## Panics:
+    /// If `operator` does not accept the number of operands (see
+    /// [`Operator::is_nary`]).
+    pub fn call(operator: Operator, operands: Vec<Expr>) -> Expr {
+        assert!(
+            operator.is_nary(operands.len()),
+            "`{}` does not accept {} operands",
+            operator.as_str(),
+            operands.len()
+        );
+        Expr(ArithmeticExpression::Node {
+            node: operator,
+            operands: operands
+                .into_iter()
+                .map(ArithmeticExpression::from)
+                .collect(),
+        })
+    }
+
+    /// `self ^ exponent`.
+    pub fn pow<T: Into<Expr>>(self, exponent: T) -> Expr {
+        Expr::call(Operator::Caret, vec![self, exponent.into()])
+    }
+
+    /// `atan2(self, x)`.
+    pub fn atan2<T: Into<Expr>>(self, x: T) -> Expr {
+        Expr::call(Operator::Atan2, vec![self, x.into()])
+    }
+
+    /// `log(base, self)`.
+    pub fn log<T: Into<Expr>>(self, base: T) -> Expr {
+        Expr::call(Operator::Log, vec![base.into(), self])
+    }
+
+    /// `hypot(self, other)`.
+    pub fn hypot<T: Into<Expr>>(self, other: T) -> Expr {
+        Expr::call(Operator::Hypot, vec![self, other.into()])
+    }
+
+    /// `min(self, other)`.
+    pub fn min<T: Into<Expr>>(self, other: T) -> Expr {
+        Expr::call(Operator::Min, vec![self, other.into()])
+    }
+
+    /// `max(self, other)`.
+    pub fn max<T: Into<Expr>>(self, other: T) -> Expr {
+        Expr::call(Operator::Max, vec![self, other.into()])
+    }
+}
+
+macro_rules! impl_functions {
+    ($($name:ident => $operator:ident),*) => {
+        impl Expr {
+            $(
+                #[doc = concat!("`", stringify!($name), "(self)`.")]
+                pub fn $name(self) -> Expr {
+                    Expr::call(Operator::$operator, vec![self])
+                }
+            )*
+        }
+    };
+}
+
+impl_functions!(
+    sqrt => Sqrt, sin => Sin, cos => Cos, tan => Tan, asin => Asin, acos => Acos,
+    atan => Atan, sinh => Sinh, cosh => Cosh, tanh => Tanh, exp => Exp, ln => Ln,
+    log10 => Log10, log2 => Log2, abs => Abs, floor => Floor, ceil => Ceil,
+    round => Round, trunc => Trunc, sign => Sign
+);
+
+impl From<ArithmeticExpression> for Expr {
+    fn from(expression: ArithmeticExpression) -> Expr {
+        Expr(expression)
+    }
+}
+
+impl From<Expr> for ArithmeticExpression {
+    fn from(expression: Expr) -> ArithmeticExpression {
+        expression.0
+    }
+}
+
+impl From<&Expr> for Expr {
+    fn from(expression: &Expr) -> Expr {
+        expression.clone()
+    }
+}
+
+impl From<f64> for Expr {
+    fn from(n: f64) -> Expr {
+        Expr::num(n)
+    }
+}
+
+macro_rules! impl_binary_operators {
+    ($($trait:ident, $method:ident => $operator:ident);*) => {
+        $(
+            impl<T: Into<Expr>> $trait<T> for Expr {
+                type Output = Expr;
+
+                fn $method(self, rhs: T) -> Expr {
+                    Expr::call(Operator::$operator, vec![self, rhs.into()])
+                }
+            }
+
+            impl<T: Into<Expr>> $trait<T> for &Expr {
+                type Output = Expr;
+
+                fn $method(self, rhs: T) -> Expr {
+                    self.clone().$method(rhs)
+                }
+            }
+
+            impl $trait<Expr> for f64 {
+                type Output = Expr;
+
+                fn $method(self, rhs: Expr) -> Expr {
+                    Expr::call(Operator::$operator, vec![self.into(), rhs])
+                }
+            }
+
+            impl $trait<&Expr> for f64 {
+                type Output = Expr;
+
+                fn $method(self, rhs: &Expr) -> Expr {
+                    Expr::call(Operator::$operator, vec![self.into(), rhs.into()])
+                }
+            }
+        )*
+    };
+}
+
+impl_binary_operators!(
+    Add, add => Plus;
+    Sub, sub => Minus;
+    Mul, mul => Star;
+    Div, div => Slash
+);
+
+impl Neg for Expr {
+    type Output = Expr;
+
+    fn neg(self) -> Expr {
+        Expr::call(Operator::Minus, vec![self])
+    }
+}
+
+impl Neg for &Expr {
+    type Output = Expr;
+
+    fn neg(self) -> Expr {
+        -self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let (x, y) = (Expr::var("x"), Expr::var("y"));
+        let cases = [
+            (&x + &y + 1.0, "x + y + 1"),
+            (&x - (&y - 2.0), "x - (y - 2)"),
+            (2.0 * &x / &y * 3.0, "2 * x / y * 3"),
+            (-&x + -(-&y), "-x + -(-y)"),
+            (x.clone().pow(y.clone().pow(2.0)), "x ^ y ^ 2"),
+            (-x.clone().pow(2.0), "-x^2"),
+            (
+                (&x * &y).sqrt() + x.clone().sin().abs(),
+                "sqrt(x * y) + abs(sin x)",
+            ),
+            (
+                x.clone().atan2(&y) + y.clone().log(2.0) + x.clone().hypot(1.0),
+                "atan2(x, y) + log(2, y) + hypot(x, 1)",
+            ),
+            (x.clone().min(&y).max(0.5), "max(min(x, y), 0.5)"),
+            (
+                Expr::call(Operator::Max, vec![x.clone(), y.clone(), Expr::num(3.0)]),
+                "max(x, y, 3)",
+            ),
+            (Expr::var("tau") * Expr::var("r"), "tau * r"),
+            (Expr::num(-2.0) * Expr::num(-0.0), "-2 * -0"),
+            (
+                Expr::num(f64::NAN) + Expr::num(f64::INFINITY),
+                "nan + 1e999",
+            ),
+            (
+                Expr::from(ArithmeticExpression::parse("x + 1").unwrap()) * 2.0,
+                "(x + 1) * 2",
+            ),
+        ];
+        for (built, input) in cases.iter() {
+            assert_eq!(
+                ArithmeticExpression::from(built.clone()),
+                ArithmeticExpression::parse(input).unwrap(),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid variable name")]
+    fn test_builder_invalid_name() {
+        Expr::var("a b");
+    }
+
+    #[test]
+    #[should_panic(expected = "`sqrt` does not accept 2 operands")]
+    fn test_builder_wrong_arity() {
+        Expr::call(Operator::Sqrt, vec![Expr::num(1.0), Expr::num(2.0)]);
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+
+use super::data_structures::{ArithmeticExpression, Operator};
+use super::error::EvalError;
+use super::options::{ArithmeticMode, EvaluationOptions, ShadowingPolicy};
+
+/// The number of operands of a node which are evaluated without allocating.
+const MAX_INLINE_OPERANDS: usize = 8;
+
+/// An arithmetic expression whose variables have been resolved to positions
+/// in a slice of values, for fast repeated evaluation.
+///
+/// It is produced by [`ArithmeticExpression::compile`].
+#[derive(Debug, Clone)]
+pub struct CompiledExpression {
+    root: CompiledNode,
+    variables: usize,
+    arithmetic: ArithmeticMode,
+}
+
+#[derive(Debug, Clone)]
+enum CompiledNode {
+    Number(f64),
+    /// The position of the value of the variable.
+    Variable(usize),
+    Unary(Operator, Box<CompiledNode>),
+    Binary(Operator, Box<(CompiledNode, CompiledNode)>),
+    Nary(Operator, Vec<CompiledNode>),
+}
+
+impl ArithmeticExpression {
+    /// Compile the expression for the variables in `variables`, with the
+    /// default [`EvaluationOptions`]. The value of each variable must be
+    /// passed to [`CompiledExpression::evaluate`] at the same position.
+    ///
+    /// Variables are looked up and constants are resolved only once, so
+    /// evaluating the compiled expression does not hash any name and does
+    /// not allocate (unless a node has more than eight operands).
+    ///
+    /// ## Errors:
+    /// An [`EvalError`] is returned if a variable of the expression is not
+    /// in `variables`, or if an error which does not depend on the values
+    /// (e.g., [`EvalError::ShadowedConstant`]) would be returned by
+    /// [`ArithmeticExpression::evaluate`].
+    ///
+    /// ## Example:
+    /// ```
+    /// use arithmetic_parser::ArithmeticExpression;
+    /// let expression = ArithmeticExpression::parse("(x+y)/(x-y)").unwrap();
+    /// let compiled = expression.compile(&["x", "y"]).unwrap();
+    /// assert_eq!(1.5, compiled.evaluate(&[5_f64, 1_f64]).unwrap());
+    /// assert_eq!(3_f64, compiled.evaluate(&[2_f64, 1_f64]).unwrap());
+    /// ```
+    pub fn compile(&self, variables: &[&str]) -> Result<CompiledExpression, EvalError> {
+        self.compile_with(variables, &EvaluationOptions::default())
+    }
+
+    /// Compile the expression like [`ArithmeticExpression::compile`], with
+    /// custom options.
+    pub fn compile_with(
+        &self,
+        variables: &[&str],
+        options: &EvaluationOptions,
+    ) -> Result<CompiledExpression, EvalError> {
+        let positions = variable_positions(variables);
+        Ok(CompiledExpression {
+            root: compile_node(self, &positions, options.shadowing).map_err(|error| {
+                self.report_missing_variables(error, |name| positions.contains_key(name))
+            })?,
+            variables: variables.len(),
+            arithmetic: options.arithmetic,
+        })
+    }
+}
+
+impl CompiledExpression {
+    /// Evaluate the expression. `values` must contain the value of each
+    /// variable, in the order used to compile the expression.
+    pub fn evaluate(&self, values: &[f64]) -> Result<f64, EvalError> {
+        if values.len() != self.variables {
+            return Err(EvalError::WrongValueCount {
+                expected: self.variables,
+                provided: values.len(),
+            });
+        }
+        self.root.evaluate(values, self.arithmetic)
+    }
+}
+
+/// The position of each variable in `variables`. The first occurrence of
+/// a name wins.
+pub(crate) fn variable_positions<'a>(variables: &[&'a str]) -> HashMap<&'a str, usize> {
+    variables
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(i, name)| (*name, i))
+        .collect()
+}
+
+fn compile_node(
+    expression: &ArithmeticExpression,
+    positions: &HashMap<&str, usize>,
+    shadowing: ShadowingPolicy,
+) -> Result<CompiledNode, EvalError> {
+    match expression {
+        ArithmeticExpression::NumberLeaf(n) => Ok(CompiledNode::Number(*n)),
+        ArithmeticExpression::VariableLeaf(x) => match positions.get(x.as_str()) {
+            Some(i) => Ok(CompiledNode::Variable(*i)),
+            None => Err(EvalError::MissingVariables {
+                names: vec![x.clone()],
+            }),
+        },
+        ArithmeticExpression::Constant { name, value } => {
+            match (positions.get(name.as_str()), shadowing) {
+                (None, _) | (Some(_), ShadowingPolicy::PreferConstant) => {
+                    Ok(CompiledNode::Number(*value))
+                }
+                (Some(i), ShadowingPolicy::PreferVariable) => Ok(CompiledNode::Variable(*i)),
+                (Some(_), ShadowingPolicy::Error) => {
+                    Err(EvalError::ShadowedConstant { name: name.clone() })
+                }
+            }
+        }
+        ArithmeticExpression::Node { node, operands } => {
+            if !node.is_nary(operands.len()) {
+                return Err(EvalError::WrongArity {
+                    operator: node.clone(),
+                    arguments: operands.len(),
+                });
+            }
+            let mut compiled_operands = Vec::with_capacity(operands.len());
+            for operand in operands {
+                compiled_operands.push(compile_node(operand, positions, shadowing)?);
+            }
+            let node = node.clone();
+            Ok(match compiled_operands.len() {
+                1 => CompiledNode::Unary(node, Box::new(compiled_operands.pop().unwrap())),
+                2 => {
+                    let right = compiled_operands.pop().unwrap();
+                    let left = compiled_operands.pop().unwrap();
+                    CompiledNode::Binary(node, Box::new((left, right)))
+                }
+                _ => CompiledNode::Nary(node, compiled_operands),
+            })
+        }
+    }
+}
+
+impl CompiledNode {
+    fn evaluate(&self, values: &[f64], arithmetic: ArithmeticMode) -> Result<f64, EvalError> {
+        match self {
+            CompiledNode::Number(n) => Ok(*n),
+            CompiledNode::Variable(i) => Ok(values[*i]),
+            CompiledNode::Unary(operator, operand) => {
+                let args = [operand.evaluate(values, arithmetic)?];
+                apply(operator, &args, arithmetic)
+            }
+            CompiledNode::Binary(operator, operands) => {
+                let args = [
+                    operands.0.evaluate(values, arithmetic)?,
+                    operands.1.evaluate(values, arithmetic)?,
+                ];
+                apply(operator, &args, arithmetic)
+            }
+            CompiledNode::Nary(operator, operands) if operands.len() <= MAX_INLINE_OPERANDS => {
+                let mut args = [0_f64; MAX_INLINE_OPERANDS];
+                for (arg, operand) in args.iter_mut().zip(operands) {
+                    *arg = operand.evaluate(values, arithmetic)?;
+                }
+                apply(operator, &args[..operands.len()], arithmetic)
+            }
+            CompiledNode::Nary(operator, operands) => {
+                let mut args = Vec::with_capacity(operands.len());
+                for operand in operands {
+                    args.push(operand.evaluate(values, arithmetic)?);
+                }
+                apply(operator, &args, arithmetic)
+            }
+        }
+    }
+}
+
+fn apply(operator: &Operator, args: &[f64], arithmetic: ArithmeticMode) -> Result<f64, EvalError> {
+    let result = operator.apply(args)?;
+    arithmetic.check(operator, args, result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arity, Context};
+
+    #[test]
+    fn test_compiled_evaluate() {
+        let inputs = [
+            "3",
+            "x",
+            "(x + 3) * 4 + (4 + y)",
+            "3 + 4 * (2 + y / (3-x) * ((5)))",
+            "2^3^2 - -x^2 * sqrt(y)",
+            "+(x, y, 1, 2, 3, 4, 5, 6, 7, 8) - max(1, 2, 3, 4, 5, 6, 7, 8, 9, x)",
+            "atan2(y, x) + log(2, y) - hypot(x, y) / pow(x, 2)",
+            "sin x * cos y - tan(x + y) + pi * e",
+            "1/(x - x) + sqrt(-y)",
+        ];
+        let values = [[1.5_f64, 4_f64], [-3_f64, 0.25], [0_f64, 1e300]];
+        for input in inputs.iter() {
+            let expression = ArithmeticExpression::parse(input).unwrap();
+            let compiled = expression.compile(&["x", "y"]).unwrap();
+            for [x, y] in values.iter() {
+                let variables = [("x", *x), ("y", *y)];
+                let expected = expression.evaluate(&variables).unwrap();
+                let result = compiled.evaluate(&[*x, *y]).unwrap();
+                assert_eq!(expected.to_bits(), result.to_bits(), "{}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compiled_options() {
+        let expression = ArithmeticExpression::parse("2 * pi + x").unwrap();
+        let compiled = |variables: &[&str], shadowing| {
+            let options = EvaluationOptions {
+                shadowing,
+                ..EvaluationOptions::default()
+            };
+            expression.compile_with(variables, &options)
+        };
+
+        assert_eq!(
+            compiled(&["pi", "x"], ShadowingPolicy::Error).unwrap_err(),
+            EvalError::ShadowedConstant {
+                name: "pi".to_string()
+            }
+        );
+        let result = compiled(&["pi", "x"], ShadowingPolicy::PreferVariable)
+            .unwrap()
+            .evaluate(&[3_f64, 1_f64]);
+        assert_eq!(result, Ok(7_f64));
+        let result = compiled(&["x", "pi"], ShadowingPolicy::PreferConstant)
+            .unwrap()
+            .evaluate(&[0_f64, 3_f64]);
+        assert_eq!(result, Ok(2_f64 * std::f64::consts::PI));
+
+        let options = EvaluationOptions {
+            arithmetic: ArithmeticMode::Checked,
+            ..EvaluationOptions::default()
+        };
+        let compiled = ArithmeticExpression::parse("1 / x")
+            .unwrap()
+            .compile_with(&["x"], &options)
+            .unwrap();
+        assert_eq!(compiled.evaluate(&[2_f64]), Ok(0.5));
+        assert_eq!(
+            compiled.evaluate(&[0_f64]),
+            Err(EvalError::DivisionByZero {
+                operator: Operator::Slash
+            })
+        );
+    }
+
+    #[test]
+    fn test_compiled_errors() {
+        let expression = ArithmeticExpression::parse("x + y").unwrap();
+        assert_eq!(
+            expression.compile(&["x", "z"]).unwrap_err(),
+            EvalError::MissingVariables {
+                names: vec!["y".to_string()]
+            }
+        );
+
+        let compiled = expression.compile(&["x", "y", "x"]).unwrap();
+        assert_eq!(compiled.evaluate(&[1_f64, 2_f64, 4_f64]), Ok(3_f64));
+        assert_eq!(
+            compiled.evaluate(&[1_f64, 2_f64]),
+            Err(EvalError::WrongValueCount {
+                expected: 3,
+                provided: 2
+            })
+        );
+
+        let malformed = ArithmeticExpression::Node {
+            node: Operator::Sqrt,
+            operands: vec![],
+        };
+        assert_eq!(
+            malformed.compile(&[]).unwrap_err(),
+            EvalError::WrongArity {
+                operator: Operator::Sqrt,
+                arguments: 0
+            }
+        );
+
+        let mut context = Context::new();
+        context.register_function("f", Arity::Exactly(1), |args: &[f64]| {
+            if args[0] < 0.0 {
+                Err("negative")
+            } else {
+                Ok(args[0])
+            }
+        });
+        let compiled = ArithmeticExpression::parse_with_context("f(x)", &context)
+            .unwrap()
+            .compile(&["x"])
+            .unwrap();
+        assert_eq!(compiled.evaluate(&[1_f64]), Ok(1_f64));
+        assert_eq!(
+            compiled.evaluate(&[-1_f64]),
+            Err(EvalError::FunctionFailed {
+                name: "f".to_string(),
+                message: "negative".to_string()
+            })
+        );
+    }
+}
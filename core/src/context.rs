@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+use super::data_structures::Operator;
+use super::parser::{find_number_literal_end, find_restricted_character_pos};
+
+/// The number of arguments accepted by a function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+    /// Between the first and the second number (both included).
+    Between(usize, usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, n: usize) -> bool {
+        match *self {
+            Arity::Exactly(m) => n == m,
+            Arity::AtLeast(m) => n >= m,
+            Arity::Between(min, max) => min <= n && n <= max,
+        }
+    }
+}
+
+type Callback = dyn Fn(&[f64]) -> Result<f64, String> + Send + Sync;
+
+/// A function registered by the user in a [`Context`].
+///
+/// The function is stored in the expressions which use it, so it does not
+/// have to be provided again at evaluation time. Two functions are equal if
+/// they come from the same registration.
+#[derive(Clone)]
+pub struct CustomFunction(Arc<FunctionDefinition>);
+
+struct FunctionDefinition {
+    name: String,
+    arity: Arity,
+    // `Sync` already rules out the shared mutability (e.g., `Cell`) which
+    // unwind safety guards against, so the expressions which store the
+    // function stay unwind safe without requiring `RefUnwindSafe` from it
+    callback: AssertUnwindSafe<Box<Callback>>,
+}
+
+impl CustomFunction {
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    pub fn arity(&self) -> Arity {
+        self.0.arity
+    }
+
+    /// Call the function. The error is the message produced by the function.
+    pub fn call(&self, args: &[f64]) -> Result<f64, String> {
+        (self.0.callback.0)(args)
+    }
+}
+
+impl fmt::Debug for CustomFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomFunction")
+            .field("name", &self.0.name)
+            .field("arity", &self.0.arity)
+            .finish()
+    }
+}
+
+impl PartialEq for CustomFunction {
+    fn eq(&self, other: &CustomFunction) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CustomFunction {}
+
+impl Hash for CustomFunction {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.name.hash(state);
+        self.0.arity.hash(state);
+    }
+}
+
+/// Additional definitions available when parsing an expression.
+///
+/// A new context contains the built-in constants `pi`, `e`, `tau`, `inf`
+/// and `nan`.
+///
+/// ## Example:
+/// ```
+/// use arithmetic_parser::{ArithmeticExpression, Arity, Context};
+/// let mut context = Context::new();
+/// context.register_function("clamp", Arity::Exactly(3), |args: &[f64]| {
+///     if args[1] <= args[2] {
+///         Ok(args[0].max(args[1]).min(args[2]))
+///     } else {
+///         Err("empty range")
+///     }
+/// });
+///
+/// let expression = ArithmeticExpression::parse_with_context("clamp(x, 0, 1)", &context).unwrap();
+/// let variables = [("x", 1.5)];
+/// assert_eq!(1_f64, expression.evaluate(&variables).unwrap());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Context {
+    functions: HashMap<String, CustomFunction>,
+    constants: HashMap<String, f64>,
+}
+
+const BUILTIN_CONSTANTS: [(&str, f64); 5] = [
+    ("pi", std::f64::consts::PI),
+    ("e", std::f64::consts::E),
+    ("tau", 2.0 * std::f64::consts::PI),
+    ("inf", f64::INFINITY),
+    ("nan", f64::NAN),
+];
+
+impl Default for Context {
+    fn default() -> Context {
+        Context {
+            functions: HashMap::new(),
+            constants: BUILTIN_CONSTANTS
+                .iter()
+                .map(|(name, value)| (name.to_string(), *value))
+                .collect(),
+        }
+    }
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context::default()
+    }
+
+    /// Register a function which can be called with the functional syntax
+    /// (e.g., `f(x, y)`, or `f x` if it accepts one argument). A function
+    /// registered with the same name is replaced.
+    ///
+    /// The function is only called with a number of arguments accepted by
+    /// `arity`. The error it returns is reported by
+    /// [`ArithmeticExpression::evaluate`](crate::ArithmeticExpression::evaluate)
+    /// as [`EvalError::FunctionFailed`](crate::EvalError::FunctionFailed).
+    ///
+    /// ## Panics:
+    /// If `name` is not a valid function name: it must not be empty, a
+    /// number or a built-in operator, and it must not contain spaces,
+    /// parenthesis, commas or symbolic operators.
+    pub fn register_function<F, E>(&mut self, name: &str, arity: Arity, function: F) -> &mut Self
+    where
+        F: Fn(&[f64]) -> Result<f64, E> + Send + Sync + 'static,
+        E: fmt::Display,
+    {
+        assert!(is_valid_name(name), "Invalid function name: {:?}", name);
+        let callback = move |args: &[f64]| function(args).map_err(|e| e.to_string());
+        self.functions.insert(
+            name.to_string(),
+            CustomFunction(Arc::new(FunctionDefinition {
+                name: name.to_string(),
+                arity,
+                callback: AssertUnwindSafe(Box::new(callback)),
+            })),
+        );
+        self
+    }
+
+    pub fn get_function(&self, name: &str) -> Option<&CustomFunction> {
+        self.functions.get(name)
+    }
+
+    /// Register a constant. Its name is replaced by its value when an
+    /// expression is parsed. A constant registered with the same name
+    /// (including a built-in one) is replaced.
+    ///
+    /// ## Panics:
+    /// If `name` is not valid, with the same rules as
+    /// [`Context::register_function`].
+    pub fn register_constant(&mut self, name: &str, value: f64) -> &mut Self {
+        assert!(is_valid_name(name), "Invalid constant name: {:?}", name);
+        self.constants.insert(name.to_string(), value);
+        self
+    }
+
+    /// Remove a constant, so that its name can be used for a variable.
+    pub fn remove_constant(&mut self, name: &str) -> &mut Self {
+        self.constants.remove(name);
+        self
+    }
+
+    pub fn get_constant(&self, name: &str) -> Option<f64> {
+        self.constants.get(name).cloned()
+    }
+}
+
+/// Whether `name` can be used for a user-defined function or a variable.
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains(char::is_whitespace)
+        && find_restricted_character_pos(name, 0, name.len()).is_none()
+        && find_number_literal_end(name, 0).is_none()
+        && Operator::get_all()
+            .iter()
+            .all(|operator| operator.as_str() != name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_name() {
+        assert!(is_valid_name("lerp"));
+        assert!(is_valid_name("f2"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("sqrt"));
+        assert!(!is_valid_name("1e5"));
+        assert!(is_valid_name("2é"));
+        assert!(!is_valid_name("f(x)"));
+        assert!(!is_valid_name("a b"));
+    }
+
+    #[test]
+    fn test_constants() {
+        let mut context = Context::new();
+        assert_eq!(context.get_constant("pi"), Some(std::f64::consts::PI));
+        assert!(context.get_constant("nan").unwrap().is_nan());
+
+        context.register_constant("g", 9.81).remove_constant("e");
+        assert_eq!(context.get_constant("g"), Some(9.81));
+        assert_eq!(context.get_constant("e"), None);
+    }
+}
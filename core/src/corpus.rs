@@ -0,0 +1,61 @@
+//! Expressions shared by the tests of the different evaluators.
+
+use std::f64::consts::{E, PI, TAU};
+
+/// The values of the variables of an expression.
+type Variables = &'static [(&'static str, f64)];
+
+/// Expressions covering every operator and syntax, with values for their
+/// variables and the value they evaluate to.
+pub(crate) const CORPUS: [(&str, Variables, f64); 38] = [
+    ("3", &[], 3.0),
+    ("x", &[("x", 4.0)], 4.0),
+    ("x + 3", &[("x", 4.0)], 7.0),
+    ("( x + 3 ) * 4 + ( 4 + y )", &[("x", 4.0), ("y", 1.0)], 33.0),
+    ("àć / 4*ü", &[("àć", 4.0), ("ü", 0.5)], 0.5),
+    (
+        "3 + 4 * (2 + yy / (3-xz) * ((5)))",
+        &[("xz", 4.0), ("yy", 1.0)],
+        -9.0,
+    ),
+    ("-x", &[("x", 4.0)], -4.0),
+    ("3 * sqrt 4 - 2 * x + +(2,3)", &[("x", 3.0)], 5.0),
+    ("* (3 + x*2, sqrt y - 1)", &[("x", 3.0), ("y", 9.0)], 18.0),
+    ("1e-5*x + 2.5E+3 - .5", &[("x", 1e5)], 2500.5),
+    ("2^3^2", &[], 512.0),
+    ("2**3**2", &[], 512.0),
+    ("-2^2", &[], -4.0),
+    ("2^-1", &[], 0.5),
+    ("2^-1^2", &[], 0.5),
+    ("3 * -2^2 + 1", &[], -11.0),
+    ("-2^2*3", &[], -12.0),
+    ("(2^3)^2", &[], 64.0),
+    ("2*3^2", &[], 18.0),
+    ("sqrt 4^2", &[], 4.0),
+    ("sin 0 + cos(0) + tan 0", &[], 1.0),
+    ("atan2(1, 0) * 2 - acos(-1)", &[], 0.0),
+    ("asin 1 - atan 1 * 2", &[], 0.0),
+    ("sinh 0 + cosh 0 + tanh 0", &[], 1.0),
+    ("exp(ln 1) + ln(exp 2)", &[], 3.0),
+    ("log10 1000 + log2 8 + log(3, 81)", &[], 10.0),
+    (
+        "abs(-2) + floor 2.7 + ceil 2.2 + round 2.5 + trunc(-2.7)",
+        &[],
+        8.0,
+    ),
+    ("sign(-3) + sign 0 + sign 4", &[], 0.0),
+    ("max(1, min(7, 3, 5), 2) + min 4", &[], 7.0),
+    ("hypot(3, 4) + pow(2, 3)", &[], 13.0),
+    ("-(8, 3) + /(9, 3)", &[], 8.0),
+    ("3 + sqrt 4 * 2", &[], 7.0),
+    ("2 * pi + e - tau / 2", &[], 2.0 * PI + E - TAU / 2.0),
+    (
+        "1 / (x - x) + sqrt(-y) * 0",
+        &[("x", 1.0), ("y", 1.0)],
+        f64::NAN,
+    ),
+    ("inf - inf", &[], f64::NAN),
+    ("x ^ 0.5 / y", &[("x", 4.0), ("y", 2.0)], 1.0),
+    ("-0 * x", &[("x", 1.0)], -0.0),
+    ("nan + x", &[("x", 1.0)], f64::NAN),
+];
@@ -0,0 +1,491 @@
+/// The tree representation of an arithmetic expression.
+///
+/// Two expressions are equal if they have the same shape and their numbers
+/// have the same bits, so that `Eq` and `Hash` are well defined: `nan` is
+/// equal to itself and `0` is different from `-0`. See
+/// [`ArithmeticExpression::structurally_equal`] for a comparison which
+/// ignores the order of the operands of commutative operators.
+///
+/// Expressions are `Send` and `Sync`, like the forms they are compiled to
+/// ([`CompiledExpression`](crate::CompiledExpression) and
+/// [`Program`](crate::Program)), so they can be shared between threads.
+#[derive(Debug, Clone)]
+pub enum ArithmeticExpression {
+    NumberLeaf(f64),
+    VariableLeaf(String),
+    /// A named constant, such as `pi`, resolved when the expression was
+    /// parsed.
+    Constant {
+        name: String,
+        value: f64,
+    },
+    Node {
+        node: Operator,
+        operands: Vec<ArithmeticExpression>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParsedToken {
+    Operand(ArithmeticExpression, Span),
+    Operator(Operator, Span),
+}
+
+/// A range of bytes in the input of the parser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A slice of the input together with its position.
+#[derive(Debug, Clone, Copy)]
+pub struct Token<'a> {
+    pub text: &'a str,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Operator {
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Caret,
+    Sqrt,
+    // Trigonometric and hyperbolic functions
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Atan2,
+    Sinh,
+    Cosh,
+    Tanh,
+    // Exponentials and logarithms
+    Exp,
+    Ln,
+    Log10,
+    Log2,
+    Log,
+    Pow,
+    // Rounding and sign
+    Abs,
+    Floor,
+    Ceil,
+    Round,
+    Trunc,
+    Sign,
+    // Functions of several arguments
+    Min,
+    Max,
+    Hypot,
+    /// A function registered in a [`Context`](crate::Context).
+    Custom(CustomFunction),
+}
+
+use lazy_static::lazy_static;
+
+use super::context::{Arity, CustomFunction};
+use super::error::EvalError;
+
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+lazy_static! {
+    static ref INFIX_OPERATORS: HashSet<char> =
+        ['+', '-', '*', '/', '^',].iter().cloned().collect();
+    static ref OPERATORS: Vec<Operator> = vec![
+        Operator::Plus,
+        Operator::Minus,
+        Operator::Star,
+        Operator::Slash,
+        Operator::Caret,
+        Operator::Sqrt,
+        Operator::Sin,
+        Operator::Cos,
+        Operator::Tan,
+        Operator::Asin,
+        Operator::Acos,
+        Operator::Atan,
+        Operator::Atan2,
+        Operator::Sinh,
+        Operator::Cosh,
+        Operator::Tanh,
+        Operator::Exp,
+        Operator::Ln,
+        Operator::Log10,
+        Operator::Log2,
+        Operator::Log,
+        Operator::Pow,
+        Operator::Abs,
+        Operator::Floor,
+        Operator::Ceil,
+        Operator::Round,
+        Operator::Trunc,
+        Operator::Sign,
+        Operator::Min,
+        Operator::Max,
+        Operator::Hypot,
+    ];
+}
+
+impl Operator {
+    pub fn get_all_infix() -> &'static HashSet<char> {
+        &INFIX_OPERATORS
+    }
+
+    pub fn get_all() -> &'static [Operator] {
+        &OPERATORS
+    }
+
+    /// The binding powers of the operator when it is placed between its
+    /// arguments, or `None` if it cannot be. The first power binds the
+    /// operand on the left and the second the one on the right: the operator
+    /// with the higher power takes an operand between two operators, so an
+    /// operator is left-associative if its left power is the lower one.
+    pub fn infix_binding_power(&self) -> Option<(u8, u8)> {
+        match self {
+            Operator::Plus | Operator::Minus => Some((1, 2)),
+            Operator::Star | Operator::Slash => Some((3, 4)),
+            Operator::Caret => Some((8, 7)),
+            _ => None,
+        }
+    }
+
+    /// The binding power of the operator when it precedes a single argument
+    /// which is not between parenthesis, or `None` if it cannot (`*`, `/`
+    /// and `^`, which only precede a list of arguments). Unary `+` and `-`
+    /// bind tighter than `*` but looser than `^`, so that `-2^2` is
+    /// `-(2^2)`, and functions bind tighter than everything.
+    pub fn prefix_binding_power(&self) -> Option<u8> {
+        match self {
+            Operator::Plus | Operator::Minus => Some(5),
+            Operator::Star | Operator::Slash | Operator::Caret => None,
+            _ => Some(9),
+        }
+    }
+
+    /// Whether the order of the operands does not change the result.
+    pub fn is_commutative(&self) -> bool {
+        matches!(
+            self,
+            Operator::Plus | Operator::Star | Operator::Min | Operator::Max | Operator::Hypot
+        )
+    }
+
+    /// Apply the operator to its arguments.
+    ///
+    /// An error is returned if the operator does not accept the number of
+    /// arguments, or if a [`CustomFunction`] fails.
+    pub fn apply(&self, args: &[f64]) -> Result<f64, EvalError> {
+        if !self.is_nary(args.len()) {
+            return Err(EvalError::WrongArity {
+                operator: self.clone(),
+                arguments: args.len(),
+            });
+        }
+        let result = match (self, args) {
+            // -0 is the identity of addition: `+(-0)` is `-0`
+            (Operator::Plus, _) => args.iter().fold(-0.0, |sum, x| sum + x),
+            (Operator::Minus, [x]) => -x,
+            (Operator::Minus, [x, y]) => x - y,
+            (Operator::Star, [x, y]) => x * y,
+            (Operator::Slash, [x, y]) => x / y,
+            (Operator::Caret, [x, y]) | (Operator::Pow, [x, y]) => x.powf(*y),
+            (Operator::Sqrt, [x]) => x.sqrt(),
+            (Operator::Sin, [x]) => x.sin(),
+            (Operator::Cos, [x]) => x.cos(),
+            (Operator::Tan, [x]) => x.tan(),
+            (Operator::Asin, [x]) => x.asin(),
+            (Operator::Acos, [x]) => x.acos(),
+            (Operator::Atan, [x]) => x.atan(),
+            (Operator::Atan2, [y, x]) => y.atan2(*x),
+            (Operator::Sinh, [x]) => x.sinh(),
+            (Operator::Cosh, [x]) => x.cosh(),
+            (Operator::Tanh, [x]) => x.tanh(),
+            (Operator::Exp, [x]) => x.exp(),
+            (Operator::Ln, [x]) => x.ln(),
+            (Operator::Log10, [x]) => x.log10(),
+            (Operator::Log2, [x]) => x.log2(),
+            (Operator::Log, [base, x]) => x.log(*base),
+            (Operator::Abs, [x]) => x.abs(),
+            (Operator::Floor, [x]) => x.floor(),
+            (Operator::Ceil, [x]) => x.ceil(),
+            (Operator::Round, [x]) => x.round(),
+            (Operator::Trunc, [x]) => x.trunc(),
+            // Zero (of either sign) and NaN are their own sign
+            (Operator::Sign, [x]) => {
+                if *x == 0.0 || x.is_nan() {
+                    *x
+                } else {
+                    x.signum()
+                }
+            }
+            (Operator::Min, [_, ..]) => args.iter().cloned().fold(f64::INFINITY, f64::min),
+            (Operator::Max, [_, ..]) => args.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+            (Operator::Hypot, [x, y]) => x.hypot(*y),
+            (Operator::Custom(function), _) => {
+                return function
+                    .call(args)
+                    .map_err(|message| EvalError::FunctionFailed {
+                        name: function.name().to_string(),
+                        message,
+                    })
+            }
+            // Not reachable, since the arity has already been checked
+            _ => {
+                return Err(EvalError::WrongArity {
+                    operator: self.clone(),
+                    arguments: args.len(),
+                })
+            }
+        };
+        Ok(result)
+    }
+
+    /// The number of arguments accepted by the operator.
+    pub fn arity(&self) -> Arity {
+        match self {
+            Operator::Plus | Operator::Min | Operator::Max => Arity::AtLeast(1),
+            Operator::Minus => Arity::Between(1, 2),
+            Operator::Star
+            | Operator::Slash
+            | Operator::Caret
+            | Operator::Atan2
+            | Operator::Log
+            | Operator::Pow
+            | Operator::Hypot => Arity::Exactly(2),
+            Operator::Sqrt
+            | Operator::Sin
+            | Operator::Cos
+            | Operator::Tan
+            | Operator::Asin
+            | Operator::Acos
+            | Operator::Atan
+            | Operator::Sinh
+            | Operator::Cosh
+            | Operator::Tanh
+            | Operator::Exp
+            | Operator::Ln
+            | Operator::Log10
+            | Operator::Log2
+            | Operator::Abs
+            | Operator::Floor
+            | Operator::Ceil
+            | Operator::Round
+            | Operator::Trunc
+            | Operator::Sign => Arity::Exactly(1),
+            Operator::Custom(function) => function.arity(),
+        }
+    }
+
+    pub fn is_nary(&self, n: usize) -> bool {
+        n > 0 && self.arity().accepts(n)
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Operator::Plus => "+",
+            Operator::Minus => "-",
+            Operator::Star => "*",
+            Operator::Slash => "/",
+            Operator::Caret => "^",
+            Operator::Sqrt => "sqrt",
+            Operator::Sin => "sin",
+            Operator::Cos => "cos",
+            Operator::Tan => "tan",
+            Operator::Asin => "asin",
+            Operator::Acos => "acos",
+            Operator::Atan => "atan",
+            Operator::Atan2 => "atan2",
+            Operator::Sinh => "sinh",
+            Operator::Cosh => "cosh",
+            Operator::Tanh => "tanh",
+            Operator::Exp => "exp",
+            Operator::Ln => "ln",
+            Operator::Log10 => "log10",
+            Operator::Log2 => "log2",
+            Operator::Log => "log",
+            Operator::Pow => "pow",
+            Operator::Abs => "abs",
+            Operator::Floor => "floor",
+            Operator::Ceil => "ceil",
+            Operator::Round => "round",
+            Operator::Trunc => "trunc",
+            Operator::Sign => "sign",
+            Operator::Min => "min",
+            Operator::Max => "max",
+            Operator::Hypot => "hypot",
+            Operator::Custom(function) => function.name(),
+        }
+    }
+}
+
+impl ArithmeticExpression {
+    /// Whether two expressions are equal, up to the order of the operands of
+    /// commutative operators (see [`Operator::is_commutative`]).
+    ///
+    /// ## Example:
+    /// ```
+    /// use arithmetic_parser::ArithmeticExpression;
+    /// let left = ArithmeticExpression::parse("x*2 + sqrt(y)").unwrap();
+    /// let right = ArithmeticExpression::parse("sqrt(y) + 2*x").unwrap();
+    /// assert!(left != right);
+    /// assert!(left.structurally_equal(&right));
+    /// ```
+    pub fn structurally_equal(&self, other: &ArithmeticExpression) -> bool {
+        match (self, other) {
+            (
+                ArithmeticExpression::Node { node, operands },
+                ArithmeticExpression::Node {
+                    node: other_node,
+                    operands: other_operands,
+                },
+            ) => {
+                if node != other_node || operands.len() != other_operands.len() {
+                    false
+                } else if node.is_commutative() {
+                    // Match every operand with a different operand of `other`
+                    let mut matched = vec![false; other_operands.len()];
+                    operands.iter().all(|operand| {
+                        let found = other_operands.iter().enumerate().position(|(i, other)| {
+                            !matched[i] && operand.structurally_equal(other)
+                        });
+                        found.map(|i| matched[i] = true).is_some()
+                    })
+                } else {
+                    operands
+                        .iter()
+                        .zip(other_operands)
+                        .all(|(operand, other)| operand.structurally_equal(other))
+                }
+            }
+            _ => self == other,
+        }
+    }
+}
+
+impl PartialEq for ArithmeticExpression {
+    fn eq(&self, other: &ArithmeticExpression) -> bool {
+        match (self, other) {
+            (ArithmeticExpression::NumberLeaf(n), ArithmeticExpression::NumberLeaf(m)) => {
+                n.to_bits() == m.to_bits()
+            }
+            (ArithmeticExpression::VariableLeaf(x), ArithmeticExpression::VariableLeaf(y)) => {
+                x == y
+            }
+            (
+                ArithmeticExpression::Constant { name, value },
+                ArithmeticExpression::Constant {
+                    name: other_name,
+                    value: other_value,
+                },
+            ) => name == other_name && value.to_bits() == other_value.to_bits(),
+            (
+                ArithmeticExpression::Node { node, operands },
+                ArithmeticExpression::Node {
+                    node: other_node,
+                    operands: other_operands,
+                },
+            ) => node == other_node && operands == other_operands,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ArithmeticExpression {}
+
+impl Hash for ArithmeticExpression {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            ArithmeticExpression::NumberLeaf(n) => n.to_bits().hash(state),
+            ArithmeticExpression::VariableLeaf(x) => x.hash(state),
+            ArithmeticExpression::Constant { name, value } => {
+                name.hash(state);
+                value.to_bits().hash(state);
+            }
+            ArithmeticExpression::Node { node, operands } => {
+                node.hash(state);
+                operands.hash(state);
+            }
+        }
+    }
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// The smallest span which contains both `self` and `other`.
+    pub fn merge(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+impl<'a> Token<'a> {
+    pub fn new(text: &'a str, start: usize) -> Token<'a> {
+        Token {
+            text,
+            span: Span::new(start, start + text.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn parse(s: &str) -> ArithmeticExpression {
+        ArithmeticExpression::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_equality() {
+        assert_eq!(parse("(x + 1) * 2"), parse("((x+1)) * 2").clone());
+        assert_ne!(parse("x + 1"), parse("1 + x"));
+        assert_ne!(parse("pi"), parse("3.141592653589793"));
+        assert_eq!(parse("nan + 0"), parse("nan + 0"));
+        assert_ne!(
+            ArithmeticExpression::NumberLeaf(0.0),
+            ArithmeticExpression::NumberLeaf(-0.0)
+        );
+
+        let mut cache = HashMap::new();
+        cache.insert(parse("sqrt(x) * nan"), 1);
+        cache.insert(parse("sqrt x * nan"), 2);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&parse("sqrt(x)*nan")), Some(&2));
+    }
+
+    #[test]
+    fn test_structurally_equal() {
+        let cases = [
+            ("x + 1", "1 + x", true),
+            ("+(x, y, y)", "+(y, x, y)", true),
+            ("+(x, x, y)", "+(x, y, y)", false),
+            ("a * (b + c)", "(c + b) * a", true),
+            ("max(1, 2, x)", "max(x, 2, 1)", true),
+            ("x - 1", "1 - x", false),
+            ("x ^ 2", "2 ^ x", false),
+            ("(x + y) + z", "x + (y + z)", false),
+            ("atan2(x, y) + 1", "1 + atan2(x, y)", true),
+            ("atan2(x, y)", "atan2(y, x)", false),
+        ];
+        for (left, right, expected) in cases.iter() {
+            assert_eq!(
+                parse(left).structurally_equal(&parse(right)),
+                *expected,
+                "{} and {}",
+                left,
+                right
+            );
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use super::data_structures::{ArithmeticExpression, Operator};
+use super::error::EvalError;
+use super::options::SimplifyOptions;
+use super::simplify::simplify;
+
+impl ArithmeticExpression {
+    /// The derivative of the expression with respect to the variable `var`,
+    /// simplified like [`ArithmeticExpression::simplify`] (e.g., `x*1` is
+    /// `x`), except that `x*0` is always `0`.
+    ///
+    /// Functions which are not differentiable everywhere are differentiated
+    /// where they are: e.g., the derivative of `abs x` is `sign(x)` and the
+    /// derivative of `floor x` is `0`. When several operands of `min` or
+    /// `max` are the result, they share the derivative equally. Constants
+    /// do not depend on any variable, even if they have the same name.
+    ///
+    /// ## Errors:
+    /// [`EvalError::NotDifferentiable`] is returned if a function registered
+    /// in a [`Context`](crate::Context) is applied to arguments which depend
+    /// on `var`, and [`EvalError::WrongArity`] if the tree is malformed.
+    ///
+    /// ## Example:
+    /// ```
+    /// use arithmetic_parser::ArithmeticExpression;
+    /// let expression = ArithmeticExpression::parse("x*x + 3*x*y").unwrap();
+    /// assert_eq!("+(x, x, 3 * y)", expression.derivative("x").unwrap().to_string());
+    /// assert_eq!("3 * x", expression.derivative("y").unwrap().to_string());
+    /// ```
+    pub fn derivative(&self, var: &str) -> Result<ArithmeticExpression, EvalError> {
+        let options = SimplifyOptions {
+            annihilate_zero: true,
+            ignore_zero_sign: true,
+            ..SimplifyOptions::default()
+        };
+        // Simplifying once at the end is enough, since the derivative of an
+        // operand is `0` exactly when the operand does not depend on `var`
+        derivative(self, var).map(|d| simplify(d, &options))
+    }
+}
+
+/// The derivative of `expression` with respect to `var`, not simplified.
+fn derivative(
+    expression: &ArithmeticExpression,
+    var: &str,
+) -> Result<ArithmeticExpression, EvalError> {
+    match expression {
+        ArithmeticExpression::NumberLeaf(_) | ArithmeticExpression::Constant { .. } => {
+            Ok(number(0.0))
+        }
+        ArithmeticExpression::VariableLeaf(x) => Ok(number(if x == var { 1.0 } else { 0.0 })),
+        ArithmeticExpression::Node { node, operands } => {
+            if !node.is_nary(operands.len()) {
+                return Err(EvalError::WrongArity {
+                    operator: node.clone(),
+                    arguments: operands.len(),
+                });
+            }
+            let mut derivatives = Vec::with_capacity(operands.len());
+            for operand in operands {
+                derivatives.push(derivative(operand, var)?);
+            }
+            if derivatives.iter().all(is_zero) {
+                return Ok(number(0.0));
+            }
+            apply_rule(node, operands, derivatives)
+        }
+    }
+}
+
+/// The derivative of `operator` applied to `u`, given the derivatives `du`
+/// of its operands. The number of operands must be valid.
+fn apply_rule(
+    operator: &Operator,
+    u: &[ArithmeticExpression],
+    mut du: Vec<ArithmeticExpression>,
+) -> Result<ArithmeticExpression, EvalError> {
+    let u0 = || u[0].clone();
+    let u1 = || u[1].clone();
+    // Derivative of a function of one argument, given f'(u)
+    let chain = |du: Vec<ArithmeticExpression>, f: ArithmeticExpression| {
+        multiply(f, du.into_iter().next().unwrap())
+    };
+
+    let result = match operator {
+        Operator::Plus => node(Operator::Plus, du),
+        Operator::Minus => node(Operator::Minus, du),
+        Operator::Star => {
+            let du1 = du.pop().unwrap();
+            let du0 = du.pop().unwrap();
+            add(multiply(du0, u1()), multiply(u0(), du1))
+        }
+        Operator::Slash => {
+            let du1 = du.pop().unwrap();
+            let du0 = du.pop().unwrap();
+            if is_zero(&du1) {
+                // (u / c)' = u' / c
+                divide(du0, u1())
+            } else {
+                divide(
+                    subtract(multiply(du0, u1()), multiply(u0(), du1)),
+                    power(u1(), number(2.0)),
+                )
+            }
+        }
+        Operator::Caret | Operator::Pow => {
+            let du1 = du.pop().unwrap();
+            let du0 = du.pop().unwrap();
+            if is_zero(&du1) {
+                // (u^c)' = c * u^(c - 1) * u'
+                multiply(
+                    multiply(u1(), power(u0(), subtract(u1(), number(1.0)))),
+                    du0,
+                )
+            } else {
+                // (u^v)' = u^v * (v' * ln(u) + v * u' / u)
+                multiply(
+                    power(u0(), u1()),
+                    add(
+                        multiply(du1, function(Operator::Ln, u0())),
+                        divide(multiply(u1(), du0), u0()),
+                    ),
+                )
+            }
+        }
+        Operator::Sqrt => divide(
+            du.pop().unwrap(),
+            multiply(number(2.0), function(Operator::Sqrt, u0())),
+        ),
+        Operator::Sin => chain(du, function(Operator::Cos, u0())),
+        Operator::Cos => chain(du, negate(function(Operator::Sin, u0()))),
+        Operator::Tan => divide(
+            du.pop().unwrap(),
+            power(function(Operator::Cos, u0()), number(2.0)),
+        ),
+        Operator::Asin => divide(du.pop().unwrap(), sqrt_one_minus_square(u0())),
+        Operator::Acos => negate(divide(du.pop().unwrap(), sqrt_one_minus_square(u0()))),
+        Operator::Atan => divide(
+            du.pop().unwrap(),
+            add(number(1.0), power(u0(), number(2.0))),
+        ),
+        Operator::Atan2 => {
+            // atan2(y, x)' = (x * y' - y * x') / (x^2 + y^2)
+            let dx = du.pop().unwrap();
+            let dy = du.pop().unwrap();
+            divide(
+                subtract(multiply(u1(), dy), multiply(u0(), dx)),
+                add(power(u1(), number(2.0)), power(u0(), number(2.0))),
+            )
+        }
+        Operator::Sinh => chain(du, function(Operator::Cosh, u0())),
+        Operator::Cosh => chain(du, function(Operator::Sinh, u0())),
+        Operator::Tanh => divide(
+            du.pop().unwrap(),
+            power(function(Operator::Cosh, u0()), number(2.0)),
+        ),
+        Operator::Exp => chain(du, function(Operator::Exp, u0())),
+        Operator::Ln => divide(du.pop().unwrap(), u0()),
+        Operator::Log10 => divide(
+            du.pop().unwrap(),
+            multiply(u0(), function(Operator::Ln, number(10.0))),
+        ),
+        Operator::Log2 => divide(
+            du.pop().unwrap(),
+            multiply(u0(), function(Operator::Ln, number(2.0))),
+        ),
+        Operator::Log => {
+            let dx = du.pop().unwrap();
+            let db = du.pop().unwrap();
+            if is_zero(&db) {
+                // log(c, x)' = x' / (x * ln(c))
+                divide(dx, multiply(u1(), function(Operator::Ln, u0())))
+            } else {
+                // log(b, x) = ln(x) / ln(b)
+                let logarithms = [function(Operator::Ln, u1()), function(Operator::Ln, u0())];
+                return apply_rule(
+                    &Operator::Slash,
+                    &logarithms,
+                    vec![divide(dx, u1()), divide(db, u0())],
+                );
+            }
+        }
+        Operator::Abs => chain(du, function(Operator::Sign, u0())),
+        Operator::Floor | Operator::Ceil | Operator::Round | Operator::Trunc | Operator::Sign => {
+            number(0.0)
+        }
+        Operator::Min | Operator::Max => {
+            if u.len() == 1 {
+                du.pop().unwrap()
+            } else {
+                // The operands equal to the result share its derivative
+                // equally: the weight of `u` is 1 - |sign(u - m)|, which is
+                // 1 if `u` is the extremum `m` and 0 otherwise
+                let extremum = node(operator.clone(), u.to_vec());
+                let weight = |u: ArithmeticExpression| {
+                    subtract(
+                        number(1.0),
+                        function(
+                            Operator::Abs,
+                            function(Operator::Sign, subtract(u, extremum.clone())),
+                        ),
+                    )
+                };
+                let weighted = u
+                    .iter()
+                    .zip(du)
+                    .filter(|(_, du)| !is_zero(du))
+                    .map(|(u, du)| multiply(weight(u.clone()), du))
+                    .collect();
+                let weights = u.iter().map(|u| weight(u.clone())).collect();
+                divide(
+                    node(Operator::Plus, weighted),
+                    node(Operator::Plus, weights),
+                )
+            }
+        }
+        Operator::Hypot => {
+            // hypot(a, b)' = (a * a' + b * b') / hypot(a, b)
+            let du1 = du.pop().unwrap();
+            let du0 = du.pop().unwrap();
+            divide(
+                add(multiply(u0(), du0), multiply(u1(), du1)),
+                node(Operator::Hypot, vec![u0(), u1()]),
+            )
+        }
+        Operator::Custom(_) => {
+            return Err(EvalError::NotDifferentiable {
+                operator: operator.clone(),
+            })
+        }
+    };
+    Ok(result)
+}
+
+fn number(n: f64) -> ArithmeticExpression {
+    ArithmeticExpression::NumberLeaf(n)
+}
+
+fn is_zero(expression: &ArithmeticExpression) -> bool {
+    matches!(expression, ArithmeticExpression::NumberLeaf(n) if *n == 0.0)
+}
+
+fn node(operator: Operator, operands: Vec<ArithmeticExpression>) -> ArithmeticExpression {
+    ArithmeticExpression::Node {
+        node: operator,
+        operands,
+    }
+}
+
+fn function(operator: Operator, x: ArithmeticExpression) -> ArithmeticExpression {
+    node(operator, vec![x])
+}
+
+fn negate(x: ArithmeticExpression) -> ArithmeticExpression {
+    node(Operator::Minus, vec![x])
+}
+
+fn add(x: ArithmeticExpression, y: ArithmeticExpression) -> ArithmeticExpression {
+    node(Operator::Plus, vec![x, y])
+}
+
+fn subtract(x: ArithmeticExpression, y: ArithmeticExpression) -> ArithmeticExpression {
+    node(Operator::Minus, vec![x, y])
+}
+
+fn multiply(x: ArithmeticExpression, y: ArithmeticExpression) -> ArithmeticExpression {
+    node(Operator::Star, vec![x, y])
+}
+
+fn divide(x: ArithmeticExpression, y: ArithmeticExpression) -> ArithmeticExpression {
+    node(Operator::Slash, vec![x, y])
+}
+
+fn power(x: ArithmeticExpression, y: ArithmeticExpression) -> ArithmeticExpression {
+    node(Operator::Caret, vec![x, y])
+}
+
+fn sqrt_one_minus_square(x: ArithmeticExpression) -> ArithmeticExpression {
+    function(Operator::Sqrt, subtract(number(1.0), power(x, number(2.0))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arity, Context};
+    use std::collections::HashMap;
+
+    fn derivative(s: &str) -> String {
+        ArithmeticExpression::parse(s)
+            .unwrap()
+            .derivative("x")
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_derivative() {
+        let cases = [
+            ("x * x", "x + x"),
+            ("3 * x + 2", "3"),
+            ("y * pi", "0"),
+            ("x ^ 3", "3 * x^2"),
+            ("2 ^ x", "2^x * 0.6931471805599453"),
+            ("sqrt x", "1 / (2 * sqrt(x))"),
+            ("sin(2 * x)", "cos(2 * x) * 2"),
+            ("-cos x", "sin(x)"),
+            ("x / y", "1 / y"),
+            ("x^2 / y", "2 * x / y"),
+            ("y / x", "-y / x^2"),
+            ("abs(x) + floor(x)", "sign(x)"),
+            ("log(2, x)", "1 / (x * 0.6931471805599453)"),
+            ("log(2, x^2)", "2 * x / (x^2 * 0.6931471805599453)"),
+        ];
+        for (input, expected) in cases.iter() {
+            assert_eq!(derivative(input), *expected, "{}", input);
+        }
+    }
+
+    /// Compare the derivatives of every operator with finite differences.
+    #[test]
+    fn test_derivative_values() {
+        let inputs = [
+            "x + 2*y - +(x, y, x) - -x",
+            "x * y / (x + 3)",
+            "x ^ 2.5 + x ^ y + y ^ x + pow(x, 3)",
+            "sqrt(x * y)",
+            "sin x * cos(x^2) + tan(y - x)",
+            "asin(x / 2) + acos(x / 3) + atan(x * y)",
+            "atan2(y, x) + atan2(x, y^2)",
+            "sinh x - cosh(2*x) + tanh(x / y)",
+            "exp(-x^2) + ln(x + y) + log10 x + log2(x * 3)",
+            "log(x, y) + log(3, x) + log(x + 1, x^2)",
+            "abs(x - 2) + floor x * ceil(y) + round(x) + trunc(y) * sign(x)",
+            "max(x, y / 2) + min(x^2, 1, y) + max(x) + min(1, x, x^3)",
+            "hypot(x, y * x)",
+        ];
+        let h = 1e-6;
+        for input in inputs.iter() {
+            let expression = ArithmeticExpression::parse(input).unwrap();
+            let derivative = expression.derivative("x").unwrap();
+            let at = |x: f64| {
+                let variables: HashMap<_, _> = [("x", x), ("y", 1.7)].iter().cloned().collect();
+                (expression.evaluate(&variables).unwrap(), variables)
+            };
+            for x in [0.7_f64, 1.3].iter() {
+                let (_, variables) = at(*x);
+                let expected = (at(x + h).0 - at(x - h).0) / (2.0 * h);
+                let result = derivative.evaluate(&variables).unwrap();
+                assert!(
+                    (result - expected).abs() < 1e-5 * (1.0 + expected.abs()),
+                    "{} at {}: {} instead of {} ({})",
+                    input,
+                    x,
+                    result,
+                    expected,
+                    derivative
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_derivative_errors() {
+        let mut context = Context::new();
+        context.register_function("f", Arity::Exactly(1), |args: &[f64]| {
+            Ok::<_, String>(args[0])
+        });
+        let expression = ArithmeticExpression::parse_with_context("f(y) * x", &context).unwrap();
+        assert_eq!(expression.derivative("x").unwrap().to_string(), "f(y)");
+        let error = expression.derivative("y").unwrap_err();
+        assert!(matches!(
+            error,
+            EvalError::NotDifferentiable {
+                operator: Operator::Custom(_)
+            }
+        ));
+
+        let malformed = node(Operator::Slash, vec![number(1.0)]);
+        assert_eq!(
+            malformed.derivative("x"),
+            Err(EvalError::WrongArity {
+                operator: Operator::Slash,
+                arguments: 1
+            })
+        );
+    }
+}
@@ -0,0 +1,391 @@
+use std::fmt;
+
+use super::context::Arity;
+use super::data_structures::{Operator, Span};
+use super::error::{EvalError, ParseError};
+use super::parser::{tokenize, DOUBLE_STAR, MAX_DEPTH};
+
+/// A human readable report of an error, in the style of the Rust compiler.
+///
+/// It shows the line of the input where the error happened, underlines the
+/// offending part with carets and, when possible, adds a hint on how to fix
+/// the expression.
+///
+/// ## Example:
+/// ```
+/// use arithmetic_parser as parser;
+/// let input = "2 + (x*4";
+/// let error = parser::ArithmeticExpression::parse(input).unwrap_err();
+/// assert_eq!(
+///     error.diagnostic(input).to_string(),
+///     "error: Parenthesis at pos 4 is not balanced
+///   |
+/// 1 | 2 + (x*4
+///   |     ^
+///   = help: did you forget a closing `)`?
+/// "
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct Diagnostic<'a> {
+    source: &'a str,
+    message: String,
+    spans: Vec<Span>,
+    help: Option<String>,
+}
+
+impl ParseError {
+    /// Build a report of this error for the input which produced it.
+    pub fn diagnostic<'a>(&self, source: &'a str) -> Diagnostic<'a> {
+        let span = self.span();
+        let help = match self {
+            ParseError::EmptyExpression { .. } => {
+                Some("an expression is expected here".to_string())
+            }
+            ParseError::UnbalancedParenthesis { .. } => {
+                if source.get(span.start..span.end) == Some(")") {
+                    Some("this `)` has no matching `(`".to_string())
+                } else {
+                    Some("did you forget a closing `)`?".to_string())
+                }
+            }
+            ParseError::AdjacentOperators { first, second, .. } => Some(format!(
+                "an operand is missing between `{}` and `{}`",
+                first.as_str(),
+                second.as_str()
+            )),
+            ParseError::AdjacentOperands { .. } => {
+                Some("did you forget an operator between these operands?".to_string())
+            }
+            ParseError::WrongArity { operator, .. } => Some(format!(
+                "`{}` needs {}",
+                operator.as_str(),
+                describe_arity(operator)
+            )),
+            ParseError::TrailingOperator { operator, .. } => {
+                Some(format!("`{}` needs an operand after it", operator.as_str()))
+            }
+            ParseError::UnexpectedToken { token, .. } => match token.as_str() {
+                "," => Some("commas can only separate the arguments of a function".to_string()),
+                _ => None,
+            },
+            ParseError::TooDeep { .. } => {
+                Some(format!("at most {} operands can be nested", MAX_DEPTH))
+            }
+        };
+        Diagnostic {
+            source,
+            message: self.to_string(),
+            spans: vec![span],
+            help,
+        }
+    }
+}
+
+impl EvalError {
+    /// Build a report of this error for the input which produced the
+    /// evaluated expression.
+    ///
+    /// The expression does not remember where its nodes were in the input,
+    /// so every occurrence of the offending variable or operator is
+    /// underlined.
+    pub fn diagnostic<'a>(&self, source: &'a str) -> Diagnostic<'a> {
+        let find_all = |name: &str| -> Vec<Span> {
+            tokenize(source)
+                .into_iter()
+                .filter(|token| token.text == name)
+                .map(|token| token.span)
+                .collect()
+        };
+        // `^` can also be written `**`
+        let find_operator = |operator: &Operator| {
+            let mut spans = find_all(operator.as_str());
+            if matches!(operator, Operator::Caret) {
+                spans.extend(find_all(DOUBLE_STAR));
+                spans.sort_by_key(|span| span.start);
+            }
+            spans
+        };
+        let (spans, help) = match self {
+            EvalError::MissingVariables { names } => {
+                let mut spans: Vec<Span> = names.iter().flat_map(|name| find_all(name)).collect();
+                spans.sort_by_key(|span| span.start);
+                let quoted: Vec<String> = names.iter().map(|name| format!("`{}`", name)).collect();
+                let help = if names.len() == 1 {
+                    format!("provide a value for {}", quoted[0])
+                } else {
+                    format!("provide values for {}", quoted.join(", "))
+                };
+                (spans, Some(help))
+            }
+            EvalError::FunctionFailed { name, .. } => (find_all(name), None),
+            EvalError::WrongArity { operator, .. } => (
+                find_operator(operator),
+                Some(format!(
+                    "`{}` needs {}",
+                    operator.as_str(),
+                    describe_arity(operator)
+                )),
+            ),
+            EvalError::DivisionByZero { operator }
+            | EvalError::DomainError { operator }
+            | EvalError::Overflow { operator }
+            | EvalError::NotANumber { operator }
+            | EvalError::NotDifferentiable { operator } => (find_operator(operator), None),
+            EvalError::WrongValueCount { .. } | EvalError::InvalidProgram { .. } => {
+                (Vec::new(), None)
+            }
+            EvalError::ColumnLength { name, .. } => (find_all(name), None),
+            EvalError::ShadowedConstant { name } => (
+                find_all(name),
+                Some(format!(
+                    "`{}` is a constant: remove it from the variables or change the shadowing policy",
+                    name
+                )),
+            ),
+        };
+        Diagnostic {
+            source,
+            message: self.to_string(),
+            spans,
+            help,
+        }
+    }
+}
+
+/// Describe the number of operands accepted by an operator, e.g.,
+/// "two operands".
+fn describe_arity(operator: &Operator) -> String {
+    const NAMES: [&str; 11] = [
+        "no", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine", "ten",
+    ];
+
+    let number = |n: usize| {
+        NAMES
+            .get(n)
+            .map_or_else(|| n.to_string(), |name| name.to_string())
+    };
+    let operands = |n: usize| if n == 1 { "operand" } else { "operands" };
+    match operator.arity() {
+        Arity::Exactly(n) => format!("{} {}", number(n), operands(n)),
+        Arity::AtLeast(0) => "any number of operands".to_string(),
+        Arity::AtLeast(n) => format!("at least {} {}", number(n), operands(n)),
+        Arity::Between(min, max) if min == max => format!("{} {}", number(min), operands(min)),
+        Arity::Between(min, max) if min + 1 == max => {
+            format!("{} or {} operands", number(min), number(max))
+        }
+        Arity::Between(min, max) => {
+            format!("between {} and {} operands", number(min), number(max))
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "error: {}", self.message)?;
+
+        // Spans which do not fit the source (e.g., because the error was
+        // produced by another input) are not underlined
+        let spans: Vec<Span> = self
+            .spans
+            .iter()
+            .filter(|span| self.source.get(span.start..span.end).is_some())
+            .copied()
+            .collect();
+        let mut lines: Vec<(usize, usize)> = Vec::new();
+        for span in &spans {
+            let line_start = self.source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+            let line_number = self.source[..line_start].matches('\n').count() + 1;
+            if !lines.contains(&(line_number, line_start)) {
+                lines.push((line_number, line_start));
+            }
+        }
+        lines.sort_unstable();
+
+        let gutter = lines.last().map_or(1, |(n, _)| n.to_string().len());
+        writeln!(f, "{:gutter$} |", "", gutter = gutter)?;
+        for (line_number, line_start) in lines {
+            let line_end = self.source[line_start..]
+                .find('\n')
+                .map_or(self.source.len(), |i| line_start + i);
+            let line = &self.source[line_start..line_end];
+            writeln!(f, "{:>gutter$} | {}", line_number, line, gutter = gutter)?;
+
+            // Carets are placed by counting characters, not bytes
+            let mut underline = String::new();
+            for (i, _) in line.char_indices() {
+                let pos = line_start + i;
+                let marked = spans.iter().any(|span| {
+                    (span.start <= pos && pos < span.end)
+                        || (span.start == span.end && span.start == pos)
+                });
+                underline.push(if marked { '^' } else { ' ' });
+            }
+            if spans
+                .iter()
+                .any(|span| span.start == span.end && span.start >= line_start + line.len())
+            {
+                underline.push('^');
+            }
+            writeln!(
+                f,
+                "{:gutter$} | {}",
+                "",
+                underline.trim_end(),
+                gutter = gutter
+            )?;
+        }
+        if let Some(help) = &self.help {
+            writeln!(f, "{:gutter$} = help: {}", "", help, gutter = gutter)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ArithmeticExpression, Context};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_diagnostic() {
+        let input = "3 * (x + 2) *";
+        let error = ArithmeticExpression::parse(input).unwrap_err();
+        assert_eq!(
+            error.diagnostic(input).to_string(),
+            "error: The expression terminates with an operator: `*`
+  |
+1 | 3 * (x + 2) *
+  |             ^
+  = help: `*` needs an operand after it
+"
+        );
+
+        let input = "1 + sqrt(4, 2)";
+        let error = ArithmeticExpression::parse(input).unwrap_err();
+        assert_eq!(
+            error.diagnostic(input).to_string(),
+            "error: `sqrt` is not an operator which accepts 2 arguments
+  |
+1 | 1 + sqrt(4, 2)
+  |     ^^^^^^^^^^
+  = help: `sqrt` needs one operand
+"
+        );
+
+        let input = "2 - / 4";
+        let error = ArithmeticExpression::parse(input).unwrap_err();
+        assert_eq!(
+            error.diagnostic(input).to_string(),
+            "error: Adjacent operators: `-`, `/`
+  |
+1 | 2 - / 4
+  |   ^^^
+  = help: an operand is missing between `-` and `/`
+"
+        );
+
+        let input = "àć * y + àć";
+        let expression = ArithmeticExpression::parse(input).unwrap();
+        let variables = [("y", 1_f64)];
+        let error = expression.evaluate(&variables).unwrap_err();
+        assert_eq!(
+            error.diagnostic(input).to_string(),
+            "error: Value for variable àć must be provided
+  |
+1 | àć * y + àć
+  | ^^       ^^
+  = help: provide a value for `àć`
+"
+        );
+
+        let error = expression
+            .evaluate(&HashMap::<&str, f64>::new())
+            .unwrap_err();
+        assert_eq!(
+            error.diagnostic(input).to_string(),
+            "error: Values for variables y, àć must be provided
+  |
+1 | àć * y + àć
+  | ^^   ^   ^^
+  = help: provide values for `y`, `àć`
+"
+        );
+
+        let input = format!("{}x", "-".repeat(300));
+        let error = ArithmeticExpression::parse(&input).unwrap_err();
+        assert!(error
+            .diagnostic(&input)
+            .to_string()
+            .ends_with("^\n  = help: at most 256 operands can be nested\n"));
+    }
+
+    #[test]
+    fn test_diagnostic_unexpected_token() {
+        let input = "1 + (2 é)";
+        let error = ParseError::UnexpectedToken {
+            token: "é".to_string(),
+            span: Span::new(7, 9),
+        };
+        assert_eq!(
+            error.diagnostic(input).to_string(),
+            "error: Unexpected token `é`
+  |
+1 | 1 + (2 é)
+  |        ^
+"
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_operator() {
+        let input = "0 ** -x + 2^x";
+        let error = EvalError::DivisionByZero {
+            operator: Operator::Caret,
+        };
+        assert_eq!(
+            error.diagnostic(input).to_string(),
+            "error: Division by zero in `^`
+  |
+1 | 0 ** -x + 2^x
+  |   ^^       ^
+"
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_mismatched_source() {
+        let error = ArithmeticExpression::parse("1 + (x * 2 + 4").unwrap_err();
+        for source in ["", "1 + é", "é"].iter() {
+            assert_eq!(
+                error.diagnostic(source).to_string(),
+                "error: Parenthesis at pos 4 is not balanced
+  |
+  = help: did you forget a closing `)`?
+"
+            );
+        }
+    }
+
+    #[test]
+    fn test_describe_arity() {
+        assert_eq!(describe_arity(&Operator::Star), "two operands");
+        assert_eq!(describe_arity(&Operator::Minus), "one or two operands");
+        assert_eq!(describe_arity(&Operator::Plus), "at least one operand");
+
+        let mut context = Context::new();
+        context
+            .register_function("f", Arity::Between(3, 4), |_: &[f64]| Ok::<_, String>(0.0))
+            .register_function("g", Arity::Between(2, 12), |_: &[f64]| Ok::<_, String>(0.0))
+            .register_function("h", Arity::AtLeast(0), |_: &[f64]| Ok::<_, String>(0.0));
+        let describe = |name| {
+            describe_arity(&Operator::Custom(
+                context.get_function(name).unwrap().clone(),
+            ))
+        };
+        assert_eq!(describe("f"), "three or four operands");
+        assert_eq!(describe("g"), "between two and 12 operands");
+        assert_eq!(describe("h"), "any number of operands");
+    }
+}
@@ -0,0 +1,265 @@
+use std::fmt;
+
+use super::data_structures::{ArithmeticExpression, Operator};
+
+/// The binding power of an expression which never needs parenthesis, such
+/// as a variable or a function call.
+const ATOMIC_POWER: u8 = u8::MAX;
+
+/// How an operator node is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Notation {
+    /// Between its two operands, e.g., `x + y`, with its infix binding
+    /// powers.
+    Infix(u8, u8),
+    /// Before its operand, e.g., `-x` or `+x`, with its prefix binding
+    /// power.
+    Prefix(u8),
+    /// As a function call, e.g., `sqrt(x)` or `+(x, y, z)`.
+    Functional,
+}
+
+fn notation(operator: &Operator, operands: &[ArithmeticExpression]) -> Notation {
+    // Only `+` and `-` are written before their operands: the other
+    // functions are written as calls
+    let prefix_power = match (operator, operands.len()) {
+        (Operator::Plus, 1) | (Operator::Minus, 1) => operator.prefix_binding_power(),
+        _ => None,
+    };
+    match (operator.infix_binding_power(), prefix_power, operands.len()) {
+        (Some((left, right)), _, 2) => Notation::Infix(left, right),
+        (_, Some(power), _) => Notation::Prefix(power),
+        _ => Notation::Functional,
+    }
+}
+
+/// The power with which `expression` binds the operand on its left, if it
+/// is written between its operands.
+fn left_power(expression: &ArithmeticExpression) -> Option<u8> {
+    match expression {
+        ArithmeticExpression::Node { node, operands } => match notation(node, operands) {
+            Notation::Infix(left, _) => Some(left),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The power with which `expression` binds the operand on its right: an
+/// infix operator which follows it takes it as its left operand only if
+/// the operator binds tighter.
+fn right_power(expression: &ArithmeticExpression) -> u8 {
+    match expression {
+        ArithmeticExpression::Node { node, operands } => match notation(node, operands) {
+            Notation::Infix(_, right) => right,
+            Notation::Prefix(power) => power,
+            Notation::Functional => ATOMIC_POWER,
+        },
+        _ => ATOMIC_POWER,
+    }
+}
+
+fn is_negative_number(expression: &ArithmeticExpression) -> bool {
+    match expression {
+        ArithmeticExpression::NumberLeaf(n) => !n.is_nan() && n.is_sign_negative(),
+        _ => false,
+    }
+}
+
+/// Write the operand of an infix or prefix operator. Negative numbers are
+/// always between parenthesis, so that their sign is not read as `-`.
+fn write_operand(
+    f: &mut fmt::Formatter<'_>,
+    operand: &ArithmeticExpression,
+    parenthesis: bool,
+) -> fmt::Result {
+    if parenthesis || is_negative_number(operand) {
+        write!(f, "({})", operand)
+    } else {
+        write!(f, "{}", operand)
+    }
+}
+
+fn write_arguments(f: &mut fmt::Formatter<'_>, operands: &[ArithmeticExpression]) -> fmt::Result {
+    write!(f, "(")?;
+    for (i, operand) in operands.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", operand)?;
+    }
+    write!(f, ")")
+}
+
+fn write_number(f: &mut fmt::Formatter<'_>, n: f64) -> fmt::Result {
+    if n.is_nan() {
+        write!(f, "nan")
+    } else if n.is_sign_negative() {
+        write!(f, "-")?;
+        write_number(f, -n)
+    } else if n.is_infinite() {
+        // A literal which overflows, so that it is parsed back as a number
+        // and not as the constant `inf`
+        write!(f, "1e999")
+    } else if n != 0.0 && !(1e-5..1e16).contains(&n) {
+        // Avoid writing hundreds of digits
+        write!(f, "{:e}", n)
+    } else {
+        write!(f, "{}", n)
+    }
+}
+
+/// Write an expression with the syntax accepted by
+/// [`ArithmeticExpression::parse`], adding parenthesis only where the
+/// binding powers of the operators require them.
+///
+/// Binary `+`, `-`, `*`, `/` and `^` are written between their operands,
+/// `+` with more than two operands is written as `+(x, y, z)` and every
+/// other function (including `sqrt`) as a function call. An expression
+/// produced by the parser is parsed back to the same tree, except that
+/// numbers are written in decimal notation, or in scientific notation if
+/// they are very large or very small (e.g., `1e-300`). Negative numbers,
+/// which the parser does not produce, are written as `-3`, between
+/// parenthesis when they are the operand of an operator: `(-3) * x`. A NaN
+/// number, which the parser does not produce either, is written as `nan`
+/// and is parsed back as the constant `nan`.
+///
+/// ## Example:
+/// ```
+/// use arithmetic_parser::ArithmeticExpression;
+/// let expression = ArithmeticExpression::parse("((x*2)) + sqrt x - (y-(1))").unwrap();
+/// assert_eq!("x * 2 + sqrt(x) - (y - 1)", expression.to_string());
+/// ```
+impl fmt::Display for ArithmeticExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (node, operands) = match self {
+            ArithmeticExpression::NumberLeaf(n) => return write_number(f, *n),
+            ArithmeticExpression::VariableLeaf(name)
+            | ArithmeticExpression::Constant { name, .. } => return write!(f, "{}", name),
+            ArithmeticExpression::Node { node, operands } => (node, operands),
+        };
+        match (notation(node, operands), operands.as_slice()) {
+            (Notation::Infix(left_binding, right_binding), [left, right]) => {
+                let left_parenthesis = left_binding > right_power(left);
+                // An operator which precedes its operands does not need
+                // parenthesis on the right (e.g., `x * -y`)
+                let right_parenthesis =
+                    left_power(right).is_some_and(|power| power <= right_binding);
+                write_operand(f, left, left_parenthesis)?;
+                match node {
+                    Operator::Caret => write!(f, "^")?,
+                    _ => write!(f, " {} ", node.as_str())?,
+                }
+                write_operand(f, right, right_parenthesis)
+            }
+            (Notation::Prefix(power), [operand]) => {
+                write!(f, "{}", node.as_str())?;
+                let parenthesis = left_power(operand).is_some_and(|left| left <= power);
+                write_operand(f, operand, parenthesis)
+            }
+            _ => {
+                write!(f, "{}", node.as_str())?;
+                write_arguments(f, operands)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ArithmeticExpression;
+
+    fn reformat(s: &str) -> String {
+        ArithmeticExpression::parse(s).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_display() {
+        let cases = [
+            ("1 + 2 + 3", "1 + 2 + 3"),
+            ("1 + (2 + 3)", "1 + (2 + 3)"),
+            ("(1 - 2) - 3", "1 - 2 - 3"),
+            ("1 - (2 - 3)", "1 - (2 - 3)"),
+            ("(a + b) * (c - d)", "(a + b) * (c - d)"),
+            ("a / (b * c)", "a / (b * c)"),
+            ("(a * b) / c", "a * b / c"),
+            ("2^3^2", "2^3^2"),
+            ("(2^3)^2", "(2^3)^2"),
+            ("-2^2", "-2^2"),
+            ("(-2)^2", "(-2)^2"),
+            ("-(x * y)", "-(x * y)"),
+            ("-x * y", "-x * y"),
+            ("x * -y", "x * -y"),
+            ("x ^ -(y + 1)", "x^-(y + 1)"),
+            ("- - x", "--x"),
+            ("sqrt(x + 1) ^ 2", "sqrt(x + 1)^2"),
+            ("sqrt x * 2", "sqrt(x) * 2"),
+            ("+(1, 2, 3) * 4", "+(1, 2, 3) * 4"),
+            ("(+(1, 2, 3)) ^ 4", "+(1, 2, 3)^4"),
+            ("+(1, 2, 3) ^ 4", "+(1, 2, 3)^4"),
+            ("max(1, x + y, min(2))", "max(1, x + y, min(2))"),
+            ("log(2, 8) + atan2(y, x)", "log(2, 8) + atan2(y, x)"),
+            ("2 * pi", "2 * pi"),
+            ("1.5e3 + .25 + 1e400", "1500 + 0.25 + 1e999"),
+            ("1e-300 * 2.5e300", "1e-300 * 2.5e300"),
+            (
+                "1e15 + 1e16 + .00001 + .000001",
+                "1000000000000000 + 1e16 + 0.00001 + 1e-6",
+            ),
+        ];
+        for (input, expected) in cases.iter() {
+            assert_eq!(reformat(input), *expected, "{}", input);
+        }
+
+        let negative = ArithmeticExpression::Node {
+            node: crate::Operator::Star,
+            operands: vec![
+                ArithmeticExpression::NumberLeaf(-3.0),
+                ArithmeticExpression::VariableLeaf("x".to_string()),
+            ],
+        };
+        assert_eq!(negative.to_string(), "(-3) * x");
+
+        let negative = ArithmeticExpression::Node {
+            node: crate::Operator::Max,
+            operands: vec![
+                ArithmeticExpression::NumberLeaf(-3.0),
+                ArithmeticExpression::Node {
+                    node: crate::Operator::Minus,
+                    operands: vec![ArithmeticExpression::NumberLeaf(-0.0)],
+                },
+            ],
+        };
+        assert_eq!(negative.to_string(), "max(-3, -(-0))");
+
+        let nan = ArithmeticExpression::NumberLeaf(f64::NAN);
+        assert_eq!(nan.to_string(), "nan");
+        assert!(matches!(
+            ArithmeticExpression::parse(&nan.to_string()).unwrap(),
+            ArithmeticExpression::Constant { value, .. } if value.is_nan()
+        ));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let inputs = [
+            "1 - (2 - (3 - 4)) - 5",
+            "a ^ b ^ (c ^ d) ^ e",
+            "-a ^ -b * -(c / d) - +e",
+            "(-a) ^ 2 - -(-(b))",
+            "+(a, b * c, -d) ^ +(e, f)",
+            "sqrt(sqrt x ^ 2 + 1) / abs(x - y) ** 0.5",
+            "min(1, 2, 3) - max(hypot(x, y), pow(2, -x))",
+            "0.1 + 6.674e-11 * tau - nan",
+            "nan * inf - -inf",
+            "1e999 - -1e999 + 1e400 * x",
+            "1e-300 + 2.5e300 - 1.7976931348623157e308 + 5e-324",
+            "123456789012345678 + 0.00001 + 0.000001234",
+        ];
+        for input in inputs.iter() {
+            let expression = ArithmeticExpression::parse(input).unwrap();
+            let reparsed = ArithmeticExpression::parse(&expression.to_string()).unwrap();
+            assert_eq!(expression, reparsed, "{}", input);
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::fmt;
+
+use super::data_structures::{Operator, Span};
+
+/// An error produced while parsing an arithmetic expression.
+///
+/// Every variant carries the byte span of the offending part of the input,
+/// so that it can be highlighted.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// The expression, or one of the arguments of a function, is empty.
+    EmptyExpression { span: Span },
+    /// A parenthesis is not matched by a corresponding one.
+    UnbalancedParenthesis { span: Span },
+    /// Two operators follow each other without an operand in between.
+    AdjacentOperators {
+        first: Operator,
+        second: Operator,
+        span: Span,
+    },
+    /// Two operands follow each other without an operator in between.
+    AdjacentOperands { span: Span },
+    /// An operator is applied to a number of arguments it does not accept.
+    WrongArity {
+        operator: Operator,
+        arguments: usize,
+        span: Span,
+    },
+    /// The expression ends with an operator.
+    TrailingOperator { operator: Operator, span: Span },
+    /// A token appears where it is not allowed (e.g., a comma outside of
+    /// the arguments of a function).
+    UnexpectedToken { token: String, span: Span },
+    /// The operands are nested too deeply (e.g., by hundreds of
+    /// parenthesis) for the parser, which would otherwise overflow the
+    /// stack. The span is the one of the operand which is too deep.
+    TooDeep { span: Span },
+}
+
+impl ParseError {
+    /// The byte span of the input where the error was detected.
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::EmptyExpression { span }
+            | ParseError::UnbalancedParenthesis { span }
+            | ParseError::AdjacentOperators { span, .. }
+            | ParseError::AdjacentOperands { span }
+            | ParseError::WrongArity { span, .. }
+            | ParseError::TrailingOperator { span, .. }
+            | ParseError::UnexpectedToken { span, .. }
+            | ParseError::TooDeep { span } => *span,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::EmptyExpression { .. } => write!(f, "The expression was empty"),
+            ParseError::UnbalancedParenthesis { span } => {
+                write!(f, "Parenthesis at pos {} is not balanced", span.start)
+            }
+            ParseError::AdjacentOperators { first, second, .. } => write!(
+                f,
+                "Adjacent operators: `{}`, `{}`",
+                first.as_str(),
+                second.as_str()
+            ),
+            ParseError::AdjacentOperands { .. } => write!(f, "Adjacent operands"),
+            ParseError::WrongArity {
+                operator,
+                arguments,
+                ..
+            } => write!(
+                f,
+                "`{}` is not an operator which accepts {} arguments",
+                operator.as_str(),
+                arguments
+            ),
+            ParseError::TrailingOperator { operator, .. } => write!(
+                f,
+                "The expression terminates with an operator: `{}`",
+                operator.as_str()
+            ),
+            ParseError::UnexpectedToken { token, .. } => write!(f, "Unexpected token `{}`", token),
+            ParseError::TooDeep { span } => {
+                write!(
+                    f,
+                    "The expression is nested too deeply at pos {}",
+                    span.start
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// An error produced while evaluating an arithmetic expression.
+///
+/// An expression tree does not remember where its nodes came from in the
+/// input, so evaluation errors identify the offending node by content
+/// instead of by span.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    /// No value was provided for some variables of the expression. All of
+    /// them are listed, in alphabetical order.
+    MissingVariables { names: Vec<String> },
+    /// A function registered in a [`Context`](crate::Context) returned an
+    /// error.
+    FunctionFailed { name: String, message: String },
+    /// A value was provided for a name which the expression uses as a
+    /// constant, and the [`ShadowingPolicy`](crate::ShadowingPolicy) does
+    /// not allow it.
+    ShadowedConstant { name: String },
+    /// An operator node has a number of operands the operator does not
+    /// accept. This can only happen in trees which were not produced by the
+    /// parser.
+    WrongArity {
+        operator: Operator,
+        arguments: usize,
+    },
+    /// In [`ArithmeticMode::Checked`](crate::ArithmeticMode::Checked), an
+    /// operator divided by zero (e.g., `1/0` or `0^-1`).
+    DivisionByZero { operator: Operator },
+    /// In [`ArithmeticMode::Checked`](crate::ArithmeticMode::Checked), an
+    /// operator was applied to finite arguments outside of its domain (e.g.,
+    /// `sqrt(-1)` or `ln 0`).
+    DomainError { operator: Operator },
+    /// In [`ArithmeticMode::Checked`](crate::ArithmeticMode::Checked), an
+    /// operator produced an infinite value (e.g., `exp 1000`).
+    Overflow { operator: Operator },
+    /// In [`ArithmeticMode::Checked`](crate::ArithmeticMode::Checked), an
+    /// operator produced NaN from arguments which were not all finite (e.g.,
+    /// `inf - inf`).
+    NotANumber { operator: Operator },
+    /// A [`CompiledExpression`](crate::CompiledExpression) was evaluated with
+    /// a number of values different from the number of its variables.
+    WrongValueCount { expected: usize, provided: usize },
+    /// A [`Program`](crate::Program) which was not produced by
+    /// [`ArithmeticExpression::to_program`](crate::ArithmeticExpression::to_program)
+    /// (e.g., a deserialized one) is not valid. The position is the one of
+    /// the offending instruction, or the number of instructions if the
+    /// program does not end with exactly one value or claims to need more
+    /// stack than it can use.
+    InvalidProgram { position: usize },
+    /// A column passed to
+    /// [`ArithmeticExpression::evaluate_batch`](crate::ArithmeticExpression::evaluate_batch)
+    /// does not have the same length as the output.
+    ColumnLength {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// [`ArithmeticExpression::derivative`](crate::ArithmeticExpression::derivative)
+    /// or
+    /// [`ArithmeticExpression::evaluate_with_gradient`](crate::ArithmeticExpression::evaluate_with_gradient)
+    /// was asked to differentiate a function registered in a
+    /// [`Context`](crate::Context), whose derivative is not known.
+    NotDifferentiable { operator: Operator },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::MissingVariables { names } => {
+                if names.len() == 1 {
+                    write!(f, "Value for variable {} must be provided", names[0])
+                } else {
+                    write!(
+                        f,
+                        "Values for variables {} must be provided",
+                        names.join(", ")
+                    )
+                }
+            }
+            EvalError::FunctionFailed { name, message } => {
+                write!(f, "Function {} failed: {}", name, message)
+            }
+            EvalError::ShadowedConstant { name } => {
+                write!(f, "A value was provided for the constant {}", name)
+            }
+            EvalError::WrongArity {
+                operator,
+                arguments,
+            } => write!(
+                f,
+                "`{}` is not an operator which accepts {} arguments",
+                operator.as_str(),
+                arguments
+            ),
+            EvalError::DivisionByZero { operator } => {
+                write!(f, "Division by zero in `{}`", operator.as_str())
+            }
+            EvalError::DomainError { operator } => write!(
+                f,
+                "Argument of `{}` is outside of its domain",
+                operator.as_str()
+            ),
+            EvalError::Overflow { operator } => {
+                write!(f, "`{}` produced an infinite value", operator.as_str())
+            }
+            EvalError::NotANumber { operator } => write!(f, "`{}` produced NaN", operator.as_str()),
+            EvalError::WrongValueCount { expected, provided } => write!(
+                f,
+                "Expected values for {} variables, but {} were provided",
+                expected, provided
+            ),
+            EvalError::InvalidProgram { position } => {
+                write!(f, "Invalid program at instruction {}", position)
+            }
+            EvalError::ColumnLength {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Column {} has {} rows, but the output has {}",
+                name, found, expected
+            ),
+            EvalError::NotDifferentiable { operator } => {
+                write!(f, "`{}` cannot be differentiated", operator.as_str())
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
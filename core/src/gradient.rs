@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+
+use super::data_structures::{ArithmeticExpression, Operator};
+use super::error::EvalError;
+use super::options::{EvaluationOptions, ShadowingPolicy};
+use super::resolver::VariableResolver;
+
+/// A value together with its partial derivatives with respect to every
+/// variable of an expression, i.e., a dual number with one infinitesimal
+/// part per variable.
+struct Dual {
+    value: f64,
+    gradient: Vec<f64>,
+    /// Whether a variable appears in the expression of the value, even if
+    /// the derivatives happen to be zero.
+    is_variable: bool,
+}
+
+impl ArithmeticExpression {
+    /// Evaluate the expression like [`ArithmeticExpression::evaluate`], and
+    /// also compute its partial derivatives with respect to every variable
+    /// which appears in it, in a single pass over the tree.
+    ///
+    /// Unlike [`ArithmeticExpression::derivative`], no new tree is built, so
+    /// the cost is proportional to the size of the expression times the
+    /// number of its variables. Functions which are not differentiable
+    /// everywhere use the same derivatives as
+    /// [`ArithmeticExpression::derivative`] (e.g., the derivative of `abs x`
+    /// is `sign(x)`); when several operands of `min` or `max` are the result,
+    /// they share the derivative equally.
+    ///
+    /// ## Errors:
+    /// The same as [`ArithmeticExpression::evaluate`], and
+    /// [`EvalError::NotDifferentiable`] if a function registered in a
+    /// [`Context`](crate::Context) is applied to arguments in which a
+    /// variable appears, whatever its value.
+    ///
+    /// ## Example:
+    /// ```
+    /// use arithmetic_parser::ArithmeticExpression;
+    /// let expression = ArithmeticExpression::parse("x^2 * y + 3").unwrap();
+    /// let variables = [("x", 2_f64), ("y", 5_f64)];
+    /// let (value, gradient) = expression.evaluate_with_gradient(&variables).unwrap();
+    /// assert_eq!(23_f64, value);
+    /// assert_eq!(20_f64, gradient["x"]);
+    /// assert_eq!(4_f64, gradient["y"]);
+    /// ```
+    pub fn evaluate_with_gradient<R: VariableResolver + ?Sized>(
+        &self,
+        variables: &R,
+    ) -> Result<(f64, HashMap<String, f64>), EvalError> {
+        self.evaluate_with_gradient_with(variables, &EvaluationOptions::default())
+    }
+
+    /// Evaluate the expression and its partial derivatives like
+    /// [`ArithmeticExpression::evaluate_with_gradient`], with custom options.
+    ///
+    /// A constant which is replaced by the value of a variable (see
+    /// [`ShadowingPolicy::PreferVariable`]) is a variable, and the derivative
+    /// with respect to it is computed.
+    pub fn evaluate_with_gradient_with<R: VariableResolver + ?Sized>(
+        &self,
+        variables: &R,
+        options: &EvaluationOptions,
+    ) -> Result<(f64, HashMap<String, f64>), EvalError> {
+        let mut resolved = Variables::default();
+        collect_variables(self, variables, options.shadowing, &mut resolved);
+        let dual = evaluate_dual(self, &resolved, options).map_err(|error| {
+            self.report_missing_variables(error, |name| variables.resolve(name).is_some())
+        })?;
+        let gradient = resolved
+            .positions
+            .into_iter()
+            .map(|(name, i)| (name.to_string(), dual.gradient[i]))
+            .collect();
+        Ok((dual.value, gradient))
+    }
+}
+
+/// The names of an expression, each resolved only once so that the
+/// evaluation is consistent even if the resolver is not.
+#[derive(Default)]
+struct Variables<'a> {
+    /// The value of every variable and constant.
+    values: HashMap<&'a str, Option<f64>>,
+    /// The position in the gradient of every variable.
+    positions: HashMap<&'a str, usize>,
+}
+
+/// Resolve every name of the expression and assign a position in the
+/// gradient to every variable.
+fn collect_variables<'a, R: VariableResolver + ?Sized>(
+    expression: &'a ArithmeticExpression,
+    variables: &R,
+    shadowing: ShadowingPolicy,
+    resolved: &mut Variables<'a>,
+) {
+    let (name, is_constant) = match expression {
+        ArithmeticExpression::NumberLeaf(_) => return,
+        ArithmeticExpression::VariableLeaf(x) => (x, false),
+        ArithmeticExpression::Constant { name, .. } => (name, true),
+        ArithmeticExpression::Node { operands, .. } => {
+            for operand in operands {
+                collect_variables(operand, variables, shadowing, resolved);
+            }
+            return;
+        }
+    };
+    let value = *resolved
+        .values
+        .entry(name)
+        .or_insert_with(|| variables.resolve(name));
+    // A constant is a variable only if it is replaced by a value
+    if is_constant && (shadowing != ShadowingPolicy::PreferVariable || value.is_none()) {
+        return;
+    }
+    let next = resolved.positions.len();
+    resolved.positions.entry(name).or_insert(next);
+}
+
+fn evaluate_dual(
+    expression: &ArithmeticExpression,
+    resolved: &Variables<'_>,
+    options: &EvaluationOptions,
+) -> Result<Dual, EvalError> {
+    let positions = &resolved.positions;
+    let resolve = |name: &str| resolved.values.get(name).copied().flatten();
+    let variable = |name: &str, value: f64| {
+        let mut gradient = vec![0_f64; positions.len()];
+        gradient[positions[name]] = 1.0;
+        Dual {
+            value,
+            gradient,
+            is_variable: true,
+        }
+    };
+    let constant = |value: f64| Dual {
+        value,
+        gradient: vec![0_f64; positions.len()],
+        is_variable: false,
+    };
+
+    match expression {
+        ArithmeticExpression::NumberLeaf(n) => Ok(constant(*n)),
+        ArithmeticExpression::VariableLeaf(x) => match resolve(x) {
+            Some(n) => Ok(variable(x, n)),
+            None => Err(EvalError::MissingVariables {
+                names: vec![x.clone()],
+            }),
+        },
+        ArithmeticExpression::Constant { name, value } => {
+            match (resolve(name), options.shadowing) {
+                (None, _) | (Some(_), ShadowingPolicy::PreferConstant) => Ok(constant(*value)),
+                (Some(n), ShadowingPolicy::PreferVariable) => Ok(variable(name, n)),
+                (Some(_), ShadowingPolicy::Error) => {
+                    Err(EvalError::ShadowedConstant { name: name.clone() })
+                }
+            }
+        }
+        ArithmeticExpression::Node { node, operands } => {
+            let mut resolved_operands = Vec::with_capacity(operands.len());
+            for operand in operands {
+                resolved_operands.push(evaluate_dual(operand, resolved, options)?);
+            }
+            let values: Vec<f64> = resolved_operands.iter().map(|x| x.value).collect();
+            let result = node.apply(&values)?;
+            let result = options.arithmetic.check(node, &values, result)?;
+
+            let mut dual = constant(result);
+            // Like for `derivative`, whether a function can be
+            // differentiated depends on the expression, not on the values
+            if !resolved_operands.iter().any(|x| x.is_variable) {
+                return Ok(dual);
+            }
+            dual.is_variable = true;
+            let partials = partial_derivatives(node, &values, result).ok_or_else(|| {
+                EvalError::NotDifferentiable {
+                    operator: node.clone(),
+                }
+            })?;
+            for (operand, partial) in resolved_operands.iter().zip(partials) {
+                for (d, operand_d) in dual.gradient.iter_mut().zip(&operand.gradient) {
+                    // Skipping the operands which do not depend on a variable
+                    // avoids NaN when their partial derivative is not finite
+                    // (e.g., the one of the exponent of `x^2` for `x < 0`)
+                    if *operand_d != 0.0 {
+                        *d += partial * operand_d;
+                    }
+                }
+            }
+            Ok(dual)
+        }
+    }
+}
+
+/// The partial derivatives of `operator` with respect to each of its
+/// arguments, given the arguments and the result. The number of arguments
+/// must be valid. `None` is returned for custom functions.
+fn partial_derivatives(operator: &Operator, args: &[f64], result: f64) -> Option<Vec<f64>> {
+    let x = args[0];
+    let partials = match operator {
+        Operator::Plus => vec![1.0; args.len()],
+        Operator::Minus if args.len() == 1 => vec![-1.0],
+        Operator::Minus => vec![1.0, -1.0],
+        Operator::Star => vec![args[1], x],
+        Operator::Slash => vec![1.0 / args[1], -x / (args[1] * args[1])],
+        Operator::Caret | Operator::Pow => {
+            vec![args[1] * x.powf(args[1] - 1.0), result * x.ln()]
+        }
+        Operator::Sqrt => vec![1.0 / (2.0 * result)],
+        Operator::Sin => vec![x.cos()],
+        Operator::Cos => vec![-x.sin()],
+        Operator::Tan => vec![1.0 / x.cos().powi(2)],
+        Operator::Asin => vec![1.0 / (1.0 - x * x).sqrt()],
+        Operator::Acos => vec![-1.0 / (1.0 - x * x).sqrt()],
+        Operator::Atan => vec![1.0 / (1.0 + x * x)],
+        Operator::Atan2 => {
+            let (y, x) = (args[0], args[1]);
+            let norm = x * x + y * y;
+            vec![x / norm, -y / norm]
+        }
+        Operator::Sinh => vec![x.cosh()],
+        Operator::Cosh => vec![x.sinh()],
+        Operator::Tanh => vec![1.0 / x.cosh().powi(2)],
+        Operator::Exp => vec![result],
+        Operator::Ln => vec![1.0 / x],
+        Operator::Log10 => vec![1.0 / (x * std::f64::consts::LN_10)],
+        Operator::Log2 => vec![1.0 / (x * std::f64::consts::LN_2)],
+        Operator::Log => {
+            // log(b, x) = ln(x) / ln(b)
+            let (base, x) = (args[0], args[1]);
+            vec![-result / (base * base.ln()), 1.0 / (x * base.ln())]
+        }
+        Operator::Abs => vec![Operator::Sign.apply(args).ok()?],
+        Operator::Floor | Operator::Ceil | Operator::Round | Operator::Trunc | Operator::Sign => {
+            vec![0.0]
+        }
+        Operator::Min | Operator::Max => {
+            let ties = args.iter().filter(|arg| **arg == result).count();
+            args.iter()
+                .map(|arg| {
+                    if *arg == result {
+                        1.0 / ties as f64
+                    } else {
+                        0.0
+                    }
+                })
+                .collect()
+        }
+        Operator::Hypot => vec![x / result, args[1] / result],
+        Operator::Custom(_) => return None,
+    };
+    Some(partials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arity, Context};
+    use std::cell::Cell;
+
+    /// The gradient must agree with the symbolic derivatives.
+    #[test]
+    fn test_evaluate_with_gradient() {
+        let inputs = [
+            "x + 2*y - +(x, y, x) - -x",
+            "x * y / (x + 3)",
+            "x ^ 2.5 + x ^ y + y ^ x + pow(x, 3)",
+            "sqrt(x * y) + sqrt(0) * x",
+            "sin x * cos(x^2) + tan(y - x)",
+            "asin(x / 2) + acos(y / 3) + atan(x * y)",
+            "atan2(y, x) + atan2(x, y^2)",
+            "sinh x - cosh(2*x) + tanh(x / y)",
+            "exp(-x^2) + ln(x + y) + log10 x + log2(y * 3)",
+            "log(x, y) + log(3, x) + log(x + 1, x^2)",
+            "abs(x - 2) + floor x * ceil(y) + round(x) + trunc(y) * sign(x)",
+            "max(x, y / 2) + min(x^2, 1, y) + max(x) + min(1, x, x^3)",
+            "hypot(x, y * x) * pi",
+        ];
+        for input in inputs.iter() {
+            let expression = ArithmeticExpression::parse(input).unwrap();
+            for (x, y) in [(0.7_f64, 1.7_f64), (1.3, 0.4)].iter() {
+                let variables = [("x", *x), ("y", *y)];
+                let (value, gradient) = expression.evaluate_with_gradient(&variables).unwrap();
+                assert_eq!(value, expression.evaluate(&variables).unwrap());
+                assert_eq!(gradient.len(), 2);
+                for name in ["x", "y"].iter() {
+                    let expected = expression
+                        .derivative(name)
+                        .unwrap()
+                        .evaluate(&variables)
+                        .unwrap();
+                    assert!(
+                        (gradient[*name] - expected).abs() < 1e-12 * (1.0 + expected.abs()),
+                        "d{} of {}: {} instead of {}",
+                        name,
+                        input,
+                        gradient[*name],
+                        expected
+                    );
+                }
+            }
+        }
+
+        let expression = ArithmeticExpression::parse("max(x, y, 1)").unwrap();
+        let variables = [("x", 1_f64), ("y", 1_f64)];
+        let (_, gradient) = expression.evaluate_with_gradient(&variables).unwrap();
+        assert_eq!(gradient["x"], 1.0 / 3.0);
+        assert_eq!(gradient["y"], 1.0 / 3.0);
+
+        let expression = ArithmeticExpression::parse("x^2").unwrap();
+        let variables = [("x", -3_f64)];
+        let (_, gradient) = expression.evaluate_with_gradient(&variables).unwrap();
+        assert_eq!(gradient["x"], -6.0);
+    }
+
+    #[test]
+    fn test_evaluate_with_gradient_options() {
+        let expression = ArithmeticExpression::parse("pi * x").unwrap();
+        let variables = [("x", 2_f64), ("pi", 3_f64)];
+
+        assert_eq!(
+            expression.evaluate_with_gradient(&variables),
+            Err(EvalError::ShadowedConstant {
+                name: "pi".to_string()
+            })
+        );
+
+        let options = EvaluationOptions {
+            shadowing: ShadowingPolicy::PreferConstant,
+            ..EvaluationOptions::default()
+        };
+        let (value, gradient) = expression
+            .evaluate_with_gradient_with(&variables, &options)
+            .unwrap();
+        assert_eq!(value, 2.0 * std::f64::consts::PI);
+        assert_eq!(gradient.len(), 1);
+        assert_eq!(gradient["x"], std::f64::consts::PI);
+
+        let options = EvaluationOptions {
+            shadowing: ShadowingPolicy::PreferVariable,
+            ..EvaluationOptions::default()
+        };
+        let (value, gradient) = expression
+            .evaluate_with_gradient_with(&variables, &options)
+            .unwrap();
+        assert_eq!(value, 6.0);
+        assert_eq!(gradient["x"], 3.0);
+        assert_eq!(gradient["pi"], 2.0);
+    }
+
+    #[test]
+    fn test_evaluate_with_gradient_errors() {
+        let mut context = Context::new();
+        context.register_function("f", Arity::Exactly(1), |args: &[f64]| {
+            Ok::<_, String>(args[0] + 1.0)
+        });
+        let expression = ArithmeticExpression::parse_with_context("f(y) * x", &context).unwrap();
+        let variables = [("x", 2_f64)];
+        assert_eq!(
+            expression.evaluate_with_gradient(&variables),
+            Err(EvalError::MissingVariables {
+                names: vec!["y".to_string()]
+            })
+        );
+
+        let variables = [("x", 2_f64), ("y", 1_f64)];
+        assert!(matches!(
+            expression.evaluate_with_gradient(&variables),
+            Err(EvalError::NotDifferentiable {
+                operator: Operator::Custom(_)
+            })
+        ));
+
+        let expression = ArithmeticExpression::parse_with_context("f(2) * x", &context).unwrap();
+        let (value, gradient) = expression.evaluate_with_gradient(&variables).unwrap();
+        assert_eq!(value, 6.0);
+        assert_eq!(gradient["x"], 3.0);
+
+        // The derivative of `y - y` is zero, but `f` is still applied to a
+        // variable
+        let expression =
+            ArithmeticExpression::parse_with_context("f(y - y) * x", &context).unwrap();
+        assert!(matches!(
+            expression.evaluate_with_gradient(&variables),
+            Err(EvalError::NotDifferentiable {
+                operator: Operator::Custom(_)
+            })
+        ));
+    }
+
+    #[test]
+    fn test_evaluate_with_gradient_ties() {
+        let expression = ArithmeticExpression::parse("max(x, y, z) + min(x, 2 * y, x)").unwrap();
+        let variables = [("x", 1_f64), ("y", 1_f64), ("z", 1_f64)];
+        let (_, gradient) = expression.evaluate_with_gradient(&variables).unwrap();
+        for name in ["x", "y", "z"].iter() {
+            let derivative = expression.derivative(name).unwrap();
+            assert_eq!(
+                derivative.evaluate(&variables).unwrap(),
+                gradient[*name],
+                "{}",
+                name
+            );
+        }
+        assert_eq!(gradient["x"], 1.0 / 3.0 + 1.0);
+        assert_eq!(gradient["y"], 1.0 / 3.0);
+    }
+
+    /// A resolver which knows the value of `pi` only the first time it is
+    /// asked.
+    struct Forgetful(Cell<bool>);
+
+    impl VariableResolver for Forgetful {
+        fn resolve(&self, name: &str) -> Option<f64> {
+            if name == "pi" && !self.0.replace(true) {
+                Some(3.0)
+            } else {
+                None
+            }
+        }
+    }
+
+    #[test]
+    fn test_evaluate_with_gradient_inconsistent_resolver() {
+        let options = EvaluationOptions {
+            shadowing: ShadowingPolicy::PreferVariable,
+            ..EvaluationOptions::default()
+        };
+        let expression = ArithmeticExpression::parse("pi * pi").unwrap();
+        let (value, gradient) = expression
+            .evaluate_with_gradient_with(&Forgetful(Cell::new(false)), &options)
+            .unwrap();
+        assert_eq!(value, 9.0);
+        assert_eq!(gradient["pi"], 6.0);
+    }
+}
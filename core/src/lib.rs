@@ -0,0 +1,504 @@
+//! The implementation of `arithmetic_parser`, in a crate of its own so
+//! that `arithmetic_parser_derive` can use the same parser at compile time.
+//!
+//! Use it through `arithmetic_parser`, which re-exports all of it.
+
+#![deny(rust_2018_idioms)]
+
+mod batch;
+mod builder;
+mod compiled;
+mod context;
+#[cfg(test)]
+mod corpus;
+mod data_structures;
+mod derivative;
+mod diagnostic;
+mod display;
+mod error;
+mod gradient;
+mod options;
+mod parser;
+mod resolver;
+#[cfg(feature = "serde")]
+pub mod serialization;
+mod simplify;
+mod variables;
+mod vm;
+
+pub use builder::Expr;
+pub use compiled::CompiledExpression;
+pub use context::{Arity, Context, CustomFunction};
+pub use data_structures::{ArithmeticExpression, Operator, Span};
+pub use diagnostic::Diagnostic;
+pub use error::{EvalError, ParseError};
+pub use options::{ArithmeticMode, EvaluationOptions, ShadowingPolicy, SimplifyOptions};
+pub use resolver::{VariableResolver, VariableValue};
+pub use vm::{Instruction, Machine, Program};
+
+pub use parser::Result;
+
+impl ArithmeticExpression {
+    /// Evaluate an arithmetic expression to produce a value.
+    ///
+    /// The values of all the variables must be provided by a
+    /// [`VariableResolver`], such as a HashMap or a slice of pairs. A
+    /// variable which is missing from the expression is ignored, but if
+    /// some variables are not resolved an [`EvalError::MissingVariables`]
+    /// listing all of them is returned.
+    /// The default [`EvaluationOptions`] are used.
+    ///
+    /// Example:
+    /// ```
+    /// use arithmetic_parser as parser;
+    /// let expression = parser::ArithmeticExpression::parse("(x+y)/(x-y)").unwrap();
+    /// let variables = [("x", 5_f64), ("y", 1_f64)];
+    /// assert_eq!(1.5, expression.evaluate(&variables).unwrap());
+    /// ```
+    pub fn evaluate<R: VariableResolver + ?Sized>(&self, variables: &R) -> Result<f64, EvalError> {
+        self.evaluate_with(variables, &EvaluationOptions::default())
+    }
+
+    /// Evaluate an arithmetic expression like [`ArithmeticExpression::evaluate`],
+    /// with custom options.
+    ///
+    /// Example:
+    /// ```
+    /// use arithmetic_parser::{ArithmeticExpression, ArithmeticMode, EvalError, EvaluationOptions, Operator};
+    /// let expression = ArithmeticExpression::parse("x / (y - 1)").unwrap();
+    /// let variables = [("x", 5_f64), ("y", 1_f64)];
+    /// assert_eq!(f64::INFINITY, expression.evaluate(&variables).unwrap());
+    ///
+    /// let options = EvaluationOptions {
+    ///     arithmetic: ArithmeticMode::Checked,
+    ///     ..EvaluationOptions::default()
+    /// };
+    /// assert_eq!(
+    ///     EvalError::DivisionByZero { operator: Operator::Slash },
+    ///     expression.evaluate_with(&variables, &options).unwrap_err()
+    /// );
+    /// ```
+    pub fn evaluate_with<R: VariableResolver + ?Sized>(
+        &self,
+        variables: &R,
+        options: &EvaluationOptions,
+    ) -> Result<f64, EvalError> {
+        evaluate_node(self, variables, options).map_err(|error| {
+            self.report_missing_variables(error, |name| variables.resolve(name).is_some())
+        })
+    }
+}
+
+fn evaluate_node<R: VariableResolver + ?Sized>(
+    expression: &ArithmeticExpression,
+    variables: &R,
+    options: &EvaluationOptions,
+) -> Result<f64, EvalError> {
+    match expression {
+        ArithmeticExpression::NumberLeaf(n) => Ok(*n),
+        ArithmeticExpression::VariableLeaf(x) => match variables.resolve(x) {
+            Some(n) => Ok(n),
+            None => Err(EvalError::MissingVariables {
+                names: vec![x.clone()],
+            }),
+        },
+        ArithmeticExpression::Constant { name, value } => {
+            match (variables.resolve(name), options.shadowing) {
+                (None, _) | (Some(_), ShadowingPolicy::PreferConstant) => Ok(*value),
+                (Some(n), ShadowingPolicy::PreferVariable) => Ok(n),
+                (Some(_), ShadowingPolicy::Error) => {
+                    Err(EvalError::ShadowedConstant { name: name.clone() })
+                }
+            }
+        }
+        ArithmeticExpression::Node { node, operands } => {
+            let mut resolved_operands = Vec::with_capacity(operands.len());
+            for operand in operands {
+                resolved_operands.push(evaluate_node(operand, variables, options)?);
+            }
+            let result = node.apply(&resolved_operands)?;
+            options.arithmetic.check(node, &resolved_operands, result)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corpus::CORPUS;
+    use proptest::prelude::*;
+    use std::collections::HashMap;
+    use std::panic::{RefUnwindSafe, UnwindSafe};
+    use std::sync::Arc;
+
+    fn parse_words(words: &[&str]) -> Result<ArithmeticExpression> {
+        ArithmeticExpression::parse(&words.join(" "))
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let tokens = ["3"];
+        assert_eq!(
+            3_f64,
+            parse_words(&tokens)
+                .unwrap()
+                .evaluate(&HashMap::<&str, f64>::new())
+                .unwrap()
+        );
+
+        let tokens = ["x"];
+        let variables = [("x", 4_f64)];
+        assert_eq!(
+            4_f64,
+            parse_words(&tokens).unwrap().evaluate(&variables).unwrap()
+        );
+
+        let tokens = ["x", "+", "3"];
+        let variables = [("x", 4_f64)];
+        assert_eq!(
+            7_f64,
+            parse_words(&tokens).unwrap().evaluate(&variables).unwrap()
+        );
+
+        let tokens = [
+            "(", "x", "+", "3", ")", "*", "4", "+", "(", "4", "+", "y", ")",
+        ];
+        let variables = [("x", 4_f64), ("y", 1_f64)];
+        assert_eq!(
+            33_f64,
+            parse_words(&tokens).unwrap().evaluate(&variables).unwrap()
+        );
+
+        for (s, variables, expected) in CORPUS.iter() {
+            let result = ArithmeticExpression::parse(s)
+                .unwrap()
+                .evaluate(*variables)
+                .unwrap();
+            assert!(
+                result == *expected || (result.is_nan() && expected.is_nan()),
+                "{}: {} instead of {}",
+                s,
+                result,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_custom_functions() {
+        let mut context = Context::new();
+        context
+            .register_function("lerp", Arity::Exactly(3), |args: &[f64]| {
+                Ok::<_, String>(args[0] + (args[1] - args[0]) * args[2])
+            })
+            .register_function("mean", Arity::AtLeast(1), |args: &[f64]| {
+                Ok::<_, String>(args.iter().sum::<f64>() / args.len() as f64)
+            })
+            .register_function("inv", Arity::Exactly(1), |args: &[f64]| {
+                if args[0] == 0.0 {
+                    Err("division by zero")
+                } else {
+                    Ok(1.0 / args[0])
+                }
+            });
+
+        let s = "lerp(2, 4, t) * mean(1, 2, 3) + inv 2";
+        let variables = [("t", 0.25)];
+        assert_eq!(
+            5.5,
+            ArithmeticExpression::parse_with_context(s, &context)
+                .unwrap()
+                .evaluate(&variables)
+                .unwrap()
+        );
+
+        assert_eq!(
+            ArithmeticExpression::parse_with_context("inv(x - 1)", &context)
+                .unwrap()
+                .evaluate(&[("x", 1_f64)])
+                .unwrap_err(),
+            EvalError::FunctionFailed {
+                name: "inv".to_string(),
+                message: "division by zero".to_string()
+            }
+        );
+
+        match ArithmeticExpression::parse_with_context("lerp(1, 2)", &context).unwrap_err() {
+            ParseError::WrongArity {
+                operator: Operator::Custom(function),
+                arguments: 2,
+                ..
+            } => assert_eq!(function.name(), "lerp"),
+            error => panic!("Unexpected error: {:?}", error),
+        }
+
+        // Without the context, the name is a variable
+        assert!(ArithmeticExpression::parse("inv 2").is_err());
+    }
+
+    #[test]
+    fn test_unwind_safe() {
+        fn assert_unwind_safe<T: UnwindSafe + RefUnwindSafe>() {}
+        assert_unwind_safe::<ArithmeticExpression>();
+        assert_unwind_safe::<Operator>();
+        assert_unwind_safe::<Context>();
+        assert_unwind_safe::<ParseError>();
+        assert_unwind_safe::<EvalError>();
+
+        // A function which is not `RefUnwindSafe` can still be registered
+        let scale: Arc<dyn Fn(f64) -> f64 + Send + Sync> = Arc::new(|x| 2.0 * x);
+        let mut context = Context::new();
+        context.register_function("scale", Arity::Exactly(1), move |args: &[f64]| {
+            Ok::<_, String>(scale(args[0]))
+        });
+        let expression = ArithmeticExpression::parse_with_context("scale 3", &context).unwrap();
+        assert_eq!(expression.evaluate(&[] as &[(&str, f64)]).unwrap(), 6.0);
+    }
+
+    #[test]
+    fn test_constants() {
+        let s = "2 * pi * r + e^0 - tau / 2 + pi2";
+        let variables = [("r", 1_f64), ("pi2", 1_f64)];
+        assert_eq!(
+            2_f64 + std::f64::consts::PI,
+            ArithmeticExpression::parse(s)
+                .unwrap()
+                .evaluate(&variables)
+                .unwrap()
+        );
+
+        let expression = ArithmeticExpression::parse("max(inf, x) + nan").unwrap();
+        let variables = [("x", 0_f64)];
+        assert!(expression.evaluate(&variables).unwrap().is_nan());
+        assert!(ArithmeticExpression::parse("NaN")
+            .unwrap()
+            .evaluate(&variables)
+            .is_err());
+
+        let mut context = Context::new();
+        context.register_constant("g", 9.81).remove_constant("e");
+        let expression = ArithmeticExpression::parse_with_context("g * e", &context).unwrap();
+        let variables = [("e", 2_f64)];
+        assert_eq!(19.62, expression.evaluate(&variables).unwrap());
+
+        let expression = ArithmeticExpression::parse("x * pi").unwrap();
+        let variables = [("x", 2_f64), ("pi", 3_f64)];
+        assert_eq!(
+            expression.evaluate(&variables).unwrap_err(),
+            EvalError::ShadowedConstant {
+                name: "pi".to_string()
+            }
+        );
+        let options = |shadowing| EvaluationOptions {
+            shadowing,
+            ..EvaluationOptions::default()
+        };
+        assert_eq!(
+            2_f64 * std::f64::consts::PI,
+            expression
+                .evaluate_with(&variables, &options(ShadowingPolicy::PreferConstant))
+                .unwrap()
+        );
+        assert_eq!(
+            6_f64,
+            expression
+                .evaluate_with(&variables, &options(ShadowingPolicy::PreferVariable))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let checked = EvaluationOptions {
+            arithmetic: ArithmeticMode::Checked,
+            ..EvaluationOptions::default()
+        };
+        let evaluate = |s, x| {
+            let variables = [("x", x)];
+            ArithmeticExpression::parse(s)
+                .unwrap()
+                .evaluate_with(&variables, &checked)
+        };
+
+        assert_eq!(Ok(2_f64), evaluate("sqrt(x) + 1/x", 1.0));
+        assert_eq!(Ok(0_f64), evaluate("1/inf", 1.0));
+        assert_eq!(
+            Err(EvalError::DivisionByZero {
+                operator: Operator::Slash
+            }),
+            evaluate("1 + x/(x - 1)", 1.0)
+        );
+        assert_eq!(
+            Err(EvalError::DivisionByZero {
+                operator: Operator::Caret
+            }),
+            evaluate("x^-1", 0.0)
+        );
+        assert_eq!(
+            Err(EvalError::DomainError {
+                operator: Operator::Sqrt
+            }),
+            evaluate("2 * sqrt x", -1.0)
+        );
+        assert_eq!(
+            Err(EvalError::DomainError {
+                operator: Operator::Ln
+            }),
+            evaluate("ln x", 0.0)
+        );
+        assert_eq!(
+            Err(EvalError::Overflow {
+                operator: Operator::Exp
+            }),
+            evaluate("exp x", 1000.0)
+        );
+        assert_eq!(
+            Err(EvalError::Overflow {
+                operator: Operator::Star
+            }),
+            evaluate("x * 10", 1e308)
+        );
+        assert_eq!(
+            Err(EvalError::NotANumber {
+                operator: Operator::Minus
+            }),
+            evaluate("inf - inf", 0.0)
+        );
+        assert_eq!(
+            Err(EvalError::NotANumber {
+                operator: Operator::Plus
+            }),
+            evaluate("x + 1", f64::NAN)
+        );
+
+        // The default mode follows IEEE 754
+        let variables = [("x", 0_f64)];
+        assert!(ArithmeticExpression::parse("sqrt(x - 1)")
+            .unwrap()
+            .evaluate(&variables)
+            .unwrap()
+            .is_nan());
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ArithmeticExpression>();
+        assert_send_sync::<CompiledExpression>();
+        assert_send_sync::<Program>();
+        assert_send_sync::<Machine>();
+        assert_send_sync::<Context>();
+        assert_send_sync::<ParseError>();
+        assert_send_sync::<EvalError>();
+    }
+
+    #[test]
+    fn test_malformed_trees() {
+        let node = |node, operands| ArithmeticExpression::Node { node, operands };
+        let number = ArithmeticExpression::NumberLeaf;
+
+        assert_eq!(
+            node(Operator::Star, vec![number(1.0)])
+                .evaluate(&HashMap::<&str, f64>::new())
+                .unwrap_err(),
+            EvalError::WrongArity {
+                operator: Operator::Star,
+                arguments: 1
+            }
+        );
+        assert!(node(Operator::Plus, vec![])
+            .evaluate(&HashMap::<&str, f64>::new())
+            .is_err());
+        assert!(node(Operator::Max, vec![])
+            .evaluate(&HashMap::<&str, f64>::new())
+            .is_err());
+        assert!(node(
+            Operator::Minus,
+            vec![node(Operator::Sqrt, vec![number(1.0), number(2.0)])]
+        )
+        .evaluate(&HashMap::<&str, f64>::new())
+        .is_err());
+    }
+
+    fn token_strategy() -> impl Strategy<Value = String> {
+        let fixed: Vec<String> = [
+            "(", ")", ",", "+", "-", "*", "/", "^", "**", "x", "y", "pi", "nan", "f", "é", "ü",
+            "π", "→",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .chain(Operator::get_all().iter().map(|o| o.as_str().to_string()))
+        .collect();
+        prop_oneof![
+            prop::sample::select(fixed),
+            (0_u32..1000).prop_map(|n| n.to_string()),
+            (0_u32..100, 0_u32..100).prop_map(|(a, b)| format!("{}.{}", a, b)),
+            (0_u32..10, -400_i32..400).prop_map(|(a, b)| format!("{}e{}", a, b)),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_no_panics(
+            tokens in prop::collection::vec((token_strategy(), any::<bool>()), 0..40),
+            x in any::<f64>(),
+            y in any::<f64>(),
+        ) {
+            let s: String = tokens
+                .iter()
+                .map(|(token, space)| if *space { format!("{} ", token) } else { token.clone() })
+                .collect();
+
+            let mut context = Context::new();
+            context.register_function("f", Arity::Between(1, 2), |args: &[f64]| {
+                if args[0] < 0.0 {
+                    Err("negative")
+                } else {
+                    Ok(args[0])
+                }
+            });
+
+            match ArithmeticExpression::parse_with_context(&s, &context) {
+                Ok(expression) => {
+                    let reparsed = ArithmeticExpression::parse_with_context(&expression.to_string(), &context);
+                    prop_assert_eq!(&expression, &reparsed.unwrap());
+
+                    let variables = [("x", x), ("y", y), ("pi", x)];
+                    let result = expression.evaluate(&variables);
+                    if let Err(error) = &result {
+                        error.diagnostic(&s).to_string();
+                    }
+
+                    // Batch evaluation must agree with the tree walker
+                    let (x_column, y_column) = ([x], [y]);
+                    let columns = [("x", &x_column[..]), ("y", &y_column[..]), ("pi", &x_column[..])]
+                        .iter()
+                        .cloned()
+                        .collect();
+                    let mut out = [0_f64];
+                    let batch_result = expression.evaluate_batch(&columns, &mut out).map(|()| out[0]);
+                    prop_assert_eq!(
+                        batch_result.map(f64::to_bits),
+                        result.clone().map(f64::to_bits)
+                    );
+
+                    // The virtual machine must agree with the tree walker
+                    if let Ok(program) = expression.to_program(&["x", "y", "pi"]) {
+                        prop_assert_eq!(
+                            program.evaluate(&[x, y, x]).map(f64::to_bits),
+                            result.clone().map(f64::to_bits)
+                        );
+                    }
+
+                    // So must the value computed with the gradient
+                    if let Ok((value, _)) = expression.evaluate_with_gradient(&variables) {
+                        prop_assert_eq!(Ok(value.to_bits()), result.map(f64::to_bits));
+                    }
+                }
+                Err(error) => {
+                    error.diagnostic(&s).to_string();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use super::data_structures::Operator;
+use super::error::EvalError;
+
+/// What happens when the variables provided for an evaluation contain a
+/// value for a name which the expression uses as a constant (e.g., `pi`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShadowingPolicy {
+    /// Return [`EvalError::ShadowedConstant`](crate::EvalError::ShadowedConstant).
+    #[default]
+    Error,
+    /// Ignore the variable and use the value of the constant.
+    PreferConstant,
+    /// Use the value of the variable instead of the constant.
+    PreferVariable,
+}
+
+/// How operators deal with results which are not finite numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ArithmeticMode {
+    /// Follow IEEE 754: e.g., `1/0` is `inf` and `sqrt(-1)` is `nan`.
+    #[default]
+    Ieee,
+    /// Return an error as soon as an operator produces an infinite or NaN
+    /// value, including when one of its arguments is already infinite or
+    /// NaN. Infinite or NaN values which are not the result of an operator
+    /// (e.g., a variable or the constant `inf` alone) are still returned.
+    Checked,
+}
+
+impl ArithmeticMode {
+    /// Check the `result` of applying `operator` to `args`.
+    pub(crate) fn check(
+        self,
+        operator: &Operator,
+        args: &[f64],
+        result: f64,
+    ) -> Result<f64, EvalError> {
+        if self == ArithmeticMode::Ieee || result.is_finite() {
+            return Ok(result);
+        }
+        let operator = operator.clone();
+        let is_log = matches!(
+            operator,
+            Operator::Ln | Operator::Log10 | Operator::Log2 | Operator::Log
+        );
+        Err(match (&operator, args) {
+            (Operator::Slash, [_, y]) if *y == 0.0 => EvalError::DivisionByZero { operator },
+            (Operator::Caret, [x, y]) | (Operator::Pow, [x, y]) if *x == 0.0 && *y < 0.0 => {
+                EvalError::DivisionByZero { operator }
+            }
+            _ if args.iter().all(|x| x.is_finite()) && (result.is_nan() || is_log) => {
+                EvalError::DomainError { operator }
+            }
+            _ if result.is_nan() => EvalError::NotANumber { operator },
+            _ => EvalError::Overflow { operator },
+        })
+    }
+}
+
+/// Options which control how an expression is evaluated.
+///
+/// ## Example:
+/// ```
+/// use arithmetic_parser::{ArithmeticExpression, EvaluationOptions, ShadowingPolicy};
+/// let expression = ArithmeticExpression::parse("2 * pi").unwrap();
+/// let variables = [("pi", 3_f64)];
+/// let options = EvaluationOptions {
+///     shadowing: ShadowingPolicy::PreferVariable,
+///     ..EvaluationOptions::default()
+/// };
+/// assert_eq!(6_f64, expression.evaluate_with(&variables, &options).unwrap());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EvaluationOptions {
+    pub shadowing: ShadowingPolicy,
+    pub arithmetic: ArithmeticMode,
+}
+
+/// Options which control how an expression is simplified.
+///
+/// By default, the simplified expression evaluates to the same value as the
+/// original one for every value of the variables, including infinite and NaN
+/// values and the sign of zero. Each option allows rewrites which do not
+/// have this property.
+///
+/// ## Example:
+/// ```
+/// use arithmetic_parser::{ArithmeticExpression, SimplifyOptions};
+/// let expression = ArithmeticExpression::parse("x * 0 + y").unwrap();
+/// assert_eq!("x * 0 + y", expression.simplify().to_string());
+///
+/// let options = SimplifyOptions {
+///     annihilate_zero: true,
+///     ignore_zero_sign: true,
+///     ..SimplifyOptions::default()
+/// };
+/// assert_eq!("y", expression.simplify_with(&options).to_string());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SimplifyOptions {
+    /// Replace `x * 0`, `0 * x` and `0 / x` with `0`, although they are NaN
+    /// when `x` is infinite or NaN (or zero, for the division).
+    pub annihilate_zero: bool,
+    /// Flatten every `+` operand of a `+`, although floating point addition
+    /// is not associative (e.g., `x + (y + z)` becomes `+(x, y, z)`, which
+    /// is `(x + y) + z`). Only the first operand is flattened otherwise.
+    pub reassociate: bool,
+    /// Replace `x + 0` and `0 + x` with `x` and `0 - x` with `-x`, although
+    /// they change the sign of a zero result: e.g., when `x` is `-0`,
+    /// `1 / (x + 0)` is `inf` but `1 / x` is `-inf`.
+    pub ignore_zero_sign: bool,
+}
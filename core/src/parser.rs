@@ -0,0 +1,823 @@
+use std::ops::Range;
+
+use lazy_static::lazy_static;
+
+use super::context::Context;
+use super::data_structures::{ArithmeticExpression, Operator, ParsedToken, Span, Token};
+use super::error::ParseError;
+
+pub type Result<T, E = ParseError> = std::result::Result<T, E>;
+
+lazy_static! {
+    pub(crate) static ref DEFAULT_CONTEXT: Context = Context::new();
+}
+
+const OPEN_PARENTHESIS: &str = "(";
+const CLOSED_PARENTHESIS: &str = ")";
+const COMMA: &str = ",";
+/// The deepest nesting of operands accepted by the parser, which parses
+/// them recursively.
+pub(crate) const MAX_DEPTH: usize = 256;
+/// Alternative spelling of [`Operator::Caret`].
+pub(crate) const DOUBLE_STAR: &str = "**";
+
+const OPEN_PARENTHESIS_CHAR: char = '(';
+const CLOSED_PARENTHESIS_CHAR: char = ')';
+const COMMA_CHAR: char = ',';
+
+impl ArithmeticExpression {
+    /// Parse an arithmetic expression and return a tree representation.
+    ///
+    /// An arithmetic expression is made of *numbers*, *variables*,
+    /// *operators* and *special characters* (parenthesis and comma).
+    /// Operators can be *functional*
+    /// if their arguments follow them (e.g., `sqrt`), or *infix* if they are
+    /// placed between their arguments (e.g., `+`). Infix operators support
+    /// precedence.
+    ///
+    /// ## Caveats:
+    /// - Spaces can be omitted around parenthesis, commas, symbolic
+    ///   operators (`+`, `-`, `*`, `/`, `^`).
+    /// - Variable names cannot contain spaces, parenthesis, commas, or symbolic
+    ///   operators.
+    /// - The names `pi`, `e`, `tau`, `inf` and `nan` are constants, not
+    ///   variables.
+    /// - Numbers can be written in decimal or scientific notation (e.g., `2`,
+    ///   `.5`, `6.674e-11`, `2.5E+3`).
+    /// - Arguments for function operators must be surrounded by parenthesis
+    ///   and separated by commas. Parenthesis can be omitted if there is only
+    ///   one argument.
+    ///
+    /// ## Grammar:
+    /// ```text
+    /// expression = operand , { infix , operand } ;
+    /// operand    = number | constant | variable
+    ///            | "(" , expression , ")"
+    ///            | operator , "(" , expression , "," , expression , { "," , expression } , ")"
+    ///            | operator , operand ;
+    /// infix      = "+" | "-" | "*" | "/" | "^" | "**" ;
+    /// operator   = infix | function ;
+    /// ```
+    /// An operator followed by a list of arguments is a function call, which
+    /// is an operand on its own: `+(1, 2, 3)^2` is `(1 + 2 + 3)^2`. An
+    /// operator followed by a single operand takes the operand and then the
+    /// infix operators which bind tighter than it, according to these binding
+    /// powers (see [`Operator::infix_binding_power`]; there are no postfix
+    /// operators):
+    ///
+    /// | Operator                | Prefix | Infix (left, right) |
+    /// |-------------------------|--------|---------------------|
+    /// | `+`, `-`                | 5      | 1, 2                |
+    /// | `*`, `/`                | -      | 3, 4                |
+    /// | `^`, `**`               | -      | 8, 7                |
+    /// | functions (e.g. `sqrt`) | 9      | -                   |
+    ///
+    /// An operand between two infix operators belongs to the one with the
+    /// higher power, so `+`, `-`, `*` and `/` are left-associative and `^` is
+    /// right-associative: `2^3^2` is `2^(3^2)`. Unary `-` binds looser than
+    /// `^` and tighter than `*`: `-2^2` is `-(2^2)` and `-x * y` is
+    /// `(-x) * y`. Functions bind tighter than everything, `^` included:
+    /// `sin x^2` is `(sin x)^2` (write `sin(x^2)` for the square of `x`), and
+    /// `sqrt 4 * 2` is `(sqrt 4) * 2`, also in `3 + sqrt 4 * 2`.
+    ///
+    /// ## Functions:
+    /// - `sqrt`, `abs`, `sign`, `exp`, `ln`, `log10`, `log2`: one argument.
+    /// - `log(base, x)`, `pow(x, y)`, `hypot(x, y)`: two arguments.
+    /// - `sin`, `cos`, `tan`, `asin`, `acos`, `atan`, `sinh`, `cosh`, `tanh`
+    ///   (angles in radians): one argument. `atan2(y, x)`: two arguments.
+    /// - `floor`, `ceil`, `round`, `trunc`: one argument.
+    /// - `min`, `max`: one or more arguments.
+    ///
+    /// ## Errors:
+    /// A [`ParseError`] is returned if the expression is malformed, or if its
+    /// operands are nested more than 256 levels deep (e.g., by parenthesis or
+    /// by `^`). The error carries the byte span of the offending part of `s`.
+    ///
+    /// ## Examples:
+    /// ```
+    /// use arithmetic_parser as parser;
+    /// parser::ArithmeticExpression::parse("3 + 2");
+    /// parser::ArithmeticExpression::parse("2 + x*4");
+    /// parser::ArithmeticExpression::parse("(1.34+sqrt x)*(2.2/(+(0.1,0.2,0.3)))");
+    ///
+    /// assert_eq!(
+    ///     parser::ArithmeticExpression::parse("sin x^2"),
+    ///     parser::ArithmeticExpression::parse("(sin x)^2"),
+    /// );
+    ///
+    /// let error = parser::ArithmeticExpression::parse("2 + (x*4").unwrap_err();
+    /// assert_eq!(parser::Span::new(4, 5), error.span());
+    /// ```
+    pub fn parse(s: &str) -> Result<ArithmeticExpression> {
+        ArithmeticExpression::parse_with_context(s, &DEFAULT_CONTEXT)
+    }
+
+    /// Parse an arithmetic expression which can also use the functions and
+    /// the constants registered in `context`. See
+    /// [`ArithmeticExpression::parse`] for the syntax.
+    pub fn parse_with_context(s: &str, context: &Context) -> Result<ArithmeticExpression> {
+        let tokens = tokenize(s);
+        let closing = match_parenthesis(&tokens);
+        let mut parser = Parser {
+            tokens: &tokens,
+            closing: &closing,
+            pos: 0,
+            span: Span::new(0, s.len()),
+            context,
+            depth: 0,
+        };
+        parser.parse_all()
+    }
+}
+
+pub(crate) fn tokenize(s: &str) -> Vec<Token<'_>> {
+    s.split_whitespace()
+        .flat_map(|x| {
+            let offset = x.as_ptr() as usize - s.as_ptr() as usize;
+            let mut tokens = Vec::new();
+            let mut pos = 0;
+            let len = x.len();
+            while pos != len {
+                if let Some(end) = find_number_literal_end(x, pos) {
+                    tokens.push(Token::new(&x[pos..end], offset + pos));
+                    pos = end;
+                } else if let Some(i) = find_restricted_character_pos(x, pos, len) {
+                    if pos != i {
+                        tokens.push(Token::new(&x[pos..i], offset + pos));
+                    }
+                    let end = if x[i..].starts_with(DOUBLE_STAR) {
+                        i + DOUBLE_STAR.len()
+                    } else {
+                        i + 1
+                    };
+                    tokens.push(Token::new(&x[i..end], offset + i));
+                    pos = end;
+                } else {
+                    tokens.push(Token::new(&x[pos..len], offset + pos));
+                    pos = len;
+                }
+            }
+            tokens
+        })
+        .collect()
+}
+
+/// If a number literal starts at `pos`, return the position where it ends.
+///
+/// A number literal is made of digits with an optional decimal point (e.g.,
+/// `2`, `2.5`, `.5`, `2.`), optionally followed by an exponent (e.g., `1e-5`,
+/// `2.5E+3`). The literal must be followed by a restricted character or by
+/// the end of `s`: otherwise it is the beginning of a longer token (e.g.,
+/// the variable `2x`).
+pub(crate) fn find_number_literal_end(s: &str, pos: usize) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let count_digits = |from: usize| {
+        bytes[from..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count()
+    };
+
+    let mut end = pos;
+    let mut mantissa_digits = count_digits(end);
+    end += mantissa_digits;
+    if bytes.get(end) == Some(&b'.') {
+        let fraction_digits = count_digits(end + 1);
+        mantissa_digits += fraction_digits;
+        end += 1 + fraction_digits;
+    }
+    if mantissa_digits == 0 {
+        return None;
+    }
+
+    if let Some(b'e') | Some(b'E') = bytes.get(end) {
+        let mut exponent_start = end + 1;
+        if let Some(b'+') | Some(b'-') = bytes.get(exponent_start) {
+            exponent_start += 1;
+        }
+        let exponent_digits = count_digits(exponent_start);
+        if exponent_digits > 0 {
+            end = exponent_start + exponent_digits;
+        }
+    }
+
+    match s[end..].chars().next() {
+        None => Some(end),
+        Some(c) if is_restricted_character(c) => Some(end),
+        Some(_) => None,
+    }
+}
+
+/// A Pratt parser over the tokens of an expression, or of one of the
+/// arguments of a function.
+struct Parser<'a, 'b> {
+    tokens: &'a [Token<'b>],
+    /// The matching parenthesis of the tokens (see [`match_parenthesis`]).
+    closing: &'a [Option<usize>],
+    pos: usize,
+    /// The region of the input which contains the tokens, used to report
+    /// errors when there are no tokens at all.
+    span: Span,
+    context: &'a Context,
+    /// The number of expressions being parsed which contain the current one.
+    depth: usize,
+}
+
+impl<'a, 'b> Parser<'a, 'b> {
+    /// Parse all the tokens.
+    fn parse_all(&mut self) -> Result<ArithmeticExpression> {
+        // No infix operator has a binding power of 0, so all the tokens are
+        // consumed
+        self.parse_expression(0).map(|(expression, _)| expression)
+    }
+
+    /// Parse the tokens of `argument`, which is a group of tokens between
+    /// parenthesis.
+    fn parse_argument(&self, argument: &Argument) -> Result<ArithmeticExpression> {
+        let (range, span) = argument;
+        Parser {
+            tokens: &self.tokens[range.clone()],
+            closing: &self.closing[range.clone()],
+            pos: 0,
+            span: *span,
+            context: self.context,
+            depth: self.depth,
+        }
+        .parse_all()
+    }
+
+    /// Parse an operand followed by infix operators which bind their left
+    /// operand tighter than `min_power`.
+    fn parse_expression(&mut self, min_power: u8) -> Result<(ArithmeticExpression, Span)> {
+        if self.depth == MAX_DEPTH {
+            let span = self
+                .tokens
+                .get(self.pos)
+                .map_or(self.span, |token| token.span);
+            return Err(ParseError::TooDeep { span });
+        }
+        self.depth += 1;
+        let result = self.parse_infix(min_power);
+        self.depth -= 1;
+        result
+    }
+
+    /// See [`Parser::parse_expression`], which limits the recursion.
+    fn parse_infix(&mut self, min_power: u8) -> Result<(ArithmeticExpression, Span)> {
+        let (mut left, mut left_span) = self.parse_operand()?;
+        while let Some(token) = self.tokens.get(self.pos).cloned() {
+            let operator = if token.text == OPEN_PARENTHESIS {
+                None
+            } else {
+                match try_parse(token, self.context)? {
+                    ParsedToken::Operator(operator, span) => Some((operator, span)),
+                    ParsedToken::Operand(..) => None,
+                }
+            };
+            // Anything but an infix operator starts another operand
+            let (operator, span, left_power, right_power) = match operator {
+                Some((operator, span)) => match operator.infix_binding_power() {
+                    Some((left_power, right_power)) => (operator, span, left_power, right_power),
+                    None => return Err(self.adjacent_operands(left_span)),
+                },
+                None => return Err(self.adjacent_operands(left_span)),
+            };
+            if left_power <= min_power {
+                break;
+            }
+            self.pos += 1;
+            if self.pos == self.tokens.len() {
+                return Err(ParseError::TrailingOperator { operator, span });
+            }
+            let (right, right_span) = self.parse_expression(right_power)?;
+            left = ArithmeticExpression::Node {
+                node: operator,
+                operands: vec![left, right],
+            };
+            left_span = left_span.merge(right_span);
+        }
+        Ok((left, left_span))
+    }
+
+    /// Parse a number, a constant, a variable, an expression between
+    /// parenthesis or an operator followed by its arguments.
+    fn parse_operand(&mut self) -> Result<(ArithmeticExpression, Span)> {
+        let token = match self.tokens.get(self.pos) {
+            Some(token) => *token,
+            None => return Err(ParseError::EmptyExpression { span: self.span }),
+        };
+        if token.text == OPEN_PARENTHESIS {
+            let (arguments, close) = self.split_group()?;
+            if arguments.len() > 1 {
+                // Only the arguments of a function are separated by commas
+                let comma = self.tokens[arguments[0].0.end];
+                return Err(ParseError::UnexpectedToken {
+                    token: comma.text.to_string(),
+                    span: comma.span,
+                });
+            }
+            let operand = self.parse_argument(&arguments[0])?;
+            let span = token.span.merge(self.tokens[close].span);
+            self.pos = close + 1;
+            return Ok((operand, span));
+        }
+
+        self.pos += 1;
+        match try_parse(token, self.context)? {
+            ParsedToken::Operand(operand, span) => Ok((operand, span)),
+            ParsedToken::Operator(operator, span) => self.parse_prefix(operator, span),
+        }
+    }
+
+    /// Parse the arguments of an operator which precedes them: either a
+    /// list of two or more arguments between parenthesis, or a single
+    /// argument which binds tighter than the operator.
+    fn parse_prefix(
+        &mut self,
+        operator: Operator,
+        span: Span,
+    ) -> Result<(ArithmeticExpression, Span)> {
+        let group = match self.tokens.get(self.pos) {
+            None => return Err(ParseError::TrailingOperator { operator, span }),
+            Some(token) if token.text == OPEN_PARENTHESIS => Some(self.split_group()?),
+            Some(_) => None,
+        };
+        let (operands, span) = match group {
+            Some((arguments, close)) if arguments.len() > 1 => {
+                let mut operands = Vec::with_capacity(arguments.len());
+                for argument in arguments.iter() {
+                    operands.push(self.parse_argument(argument)?);
+                }
+                self.pos = close + 1;
+                (operands, span.merge(self.tokens[close].span))
+            }
+            _ => {
+                let power = match operator.prefix_binding_power() {
+                    Some(power) => power,
+                    None => return Err(self.missing_operand(operator, span)),
+                };
+                let (operand, operand_span) = self.parse_expression(power)?;
+                (vec![operand], span.merge(operand_span))
+            }
+        };
+        if !operator.is_nary(operands.len()) {
+            return Err(ParseError::WrongArity {
+                operator,
+                arguments: operands.len(),
+                span,
+            });
+        }
+        Ok((
+            ArithmeticExpression::Node {
+                node: operator,
+                operands,
+            },
+            span,
+        ))
+    }
+
+    /// Split the tokens between the parenthesis at the current position
+    /// into arguments, and return them with the position of the closing
+    /// parenthesis.
+    fn split_group(&self) -> Result<(Vec<Argument>, usize)> {
+        let close = match self.closing[self.pos] {
+            Some(distance) => self.pos + distance,
+            None => {
+                return Err(ParseError::UnbalancedParenthesis {
+                    span: self.tokens[self.pos].span,
+                })
+            }
+        };
+        Ok((
+            split_arguments(self.tokens, self.closing, self.pos, close),
+            close,
+        ))
+    }
+
+    /// The error for an operator at `span` which cannot precede a single
+    /// operand, such as `*`: either it follows another operator, or it
+    /// misses its left operand.
+    fn missing_operand(&mut self, operator: Operator, span: Span) -> ParseError {
+        let previous = match self.pos {
+            0 | 1 => None,
+            pos => Some(self.tokens[pos - 2]),
+        };
+        if let Some(previous) = previous {
+            if let Some(first) = try_parse_operator(previous.text, self.context) {
+                return ParseError::AdjacentOperators {
+                    first,
+                    second: operator,
+                    span: previous.span.merge(span),
+                };
+            }
+        }
+        match self.parse_operand() {
+            Ok((_, operand_span)) => ParseError::WrongArity {
+                operator,
+                arguments: 1,
+                span: span.merge(operand_span),
+            },
+            Err(error) => error,
+        }
+    }
+
+    /// The error for an operand at the current position, which follows the
+    /// operand at `left_span` without an operator in between.
+    fn adjacent_operands(&mut self, left_span: Span) -> ParseError {
+        match self.parse_operand() {
+            Ok((_, span)) => ParseError::AdjacentOperands {
+                span: left_span.merge(span),
+            },
+            Err(error) => error,
+        }
+    }
+}
+
+/// The positions of the tokens of an argument of a function, with the
+/// region of the input they occupy.
+type Argument = (Range<usize>, Span);
+
+/// Split the tokens between the parenthesis at positions `open` and `close`
+/// into comma-separated arguments, each with the span it covers. Commas
+/// inside nested parenthesis belong to the nested arguments, which are
+/// skipped without being scanned.
+fn split_arguments(
+    tokens: &[Token<'_>],
+    closing: &[Option<usize>],
+    open: usize,
+    close: usize,
+) -> Vec<Argument> {
+    let mut arguments = Vec::new();
+    let mut start = open + 1;
+    let mut pos = open + 1;
+    while pos <= close {
+        if pos == close || tokens[pos].text == COMMA {
+            let span = if start < pos {
+                tokens[start].span.merge(tokens[pos - 1].span)
+            } else {
+                Span::new(tokens[start - 1].span.end, tokens[pos].span.start)
+            };
+            arguments.push((start..pos, span));
+            start = pos + 1;
+        } else if let Some(distance) = closing[pos] {
+            pos += distance;
+        }
+        pos += 1;
+    }
+    arguments
+}
+
+fn try_parse(token: Token<'_>, context: &Context) -> Result<ParsedToken> {
+    if token.text == CLOSED_PARENTHESIS {
+        return Err(ParseError::UnbalancedParenthesis { span: token.span });
+    }
+    if token.text == COMMA {
+        return Err(ParseError::UnexpectedToken {
+            token: token.text.to_string(),
+            span: token.span,
+        });
+    }
+
+    if let Some(operator) = try_parse_operator(token.text, context) {
+        return Ok(ParsedToken::Operator(operator, token.span));
+    }
+
+    if let Some(value) = context.get_constant(token.text) {
+        return Ok(ParsedToken::Operand(
+            ArithmeticExpression::Constant {
+                name: token.text.to_string(),
+                value,
+            },
+            token.span,
+        ));
+    }
+
+    if let Some(number) = try_parse_number(token.text) {
+        return Ok(ParsedToken::Operand(
+            ArithmeticExpression::NumberLeaf(number),
+            token.span,
+        ));
+    }
+
+    if let Some(variable) = try_parse_variable(token.text) {
+        return Ok(ParsedToken::Operand(
+            ArithmeticExpression::VariableLeaf(variable),
+            token.span,
+        ));
+    }
+
+    Err(ParseError::UnexpectedToken {
+        token: token.text.to_string(),
+        span: token.span,
+    })
+}
+
+fn try_parse_number(token: &str) -> Option<f64> {
+    // Reject the special values accepted by `f64::from_str` (e.g., `NaN`)
+    if find_number_literal_end(token, 0) == Some(token.len()) {
+        token.parse::<f64>().ok()
+    } else {
+        None
+    }
+}
+
+fn try_parse_variable(token: &str) -> Option<String> {
+    Some(token.to_string())
+}
+
+pub(crate) fn try_parse_operator(token: &str, context: &Context) -> Option<Operator> {
+    if token == DOUBLE_STAR {
+        return Some(Operator::Caret);
+    }
+    for operator in Operator::get_all() {
+        if operator.as_str() == token {
+            return Some(operator.clone());
+        }
+    }
+    context
+        .get_function(token)
+        .map(|function| Operator::Custom(function.clone()))
+}
+
+pub(crate) fn find_restricted_character_pos(s: &str, left: usize, right: usize) -> Option<usize> {
+    s[left..right]
+        .find(is_restricted_character)
+        .map(|i| i + left)
+}
+
+fn is_restricted_character(c: char) -> bool {
+    c == OPEN_PARENTHESIS_CHAR
+        || c == CLOSED_PARENTHESIS_CHAR
+        || c == COMMA_CHAR
+        || Operator::get_all_infix().contains(&c)
+}
+
+/// For each opening parenthesis of `tokens`, the distance to the matching
+/// closing one, or `None` if it is not closed. A distance is kept instead
+/// of a position so that it stays valid in a subslice of the tokens.
+fn match_parenthesis(tokens: &[Token<'_>]) -> Vec<Option<usize>> {
+    let mut closing = vec![None; tokens.len()];
+    let mut open = Vec::new();
+    for (pos, token) in tokens.iter().enumerate() {
+        if token.text == OPEN_PARENTHESIS {
+            open.push(pos);
+        } else if token.text == CLOSED_PARENTHESIS {
+            if let Some(start) = open.pop() {
+                closing[start] = Some(pos - start);
+            }
+        }
+    }
+    closing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_parenthesis() {
+        let tokens = tokenize("a((f)(b fer))");
+        assert_eq!(
+            match_parenthesis(&tokens),
+            [
+                None,
+                Some(8),
+                Some(2),
+                None,
+                None,
+                Some(3),
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+
+        let tokens = tokenize("a((f)(b fer)");
+        assert_eq!(match_parenthesis(&tokens)[1], None);
+        assert_eq!(
+            ArithmeticExpression::parse("a((f)(b fer)").unwrap_err(),
+            ParseError::UnbalancedParenthesis {
+                span: Span::new(1, 2)
+            }
+        );
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let texts = |s| -> Vec<_> { tokenize(s).into_iter().map(|t| t.text).collect() };
+
+        assert_eq!(texts("6.674e-11*m"), ["6.674e-11", "*", "m"]);
+        assert_eq!(texts("2.5E+3 -.5"), ["2.5E+3", "-", ".5"]);
+        assert_eq!(texts("1e-x+2."), ["1e", "-", "x", "+", "2."]);
+        assert_eq!(texts("2x-1e5e"), ["2x", "-", "1e5e"]);
+        assert_eq!(texts("2é"), ["2é"]);
+        assert_eq!(texts("1e5ü*2"), ["1e5ü", "*", "2"]);
+        assert_eq!(texts("2.5ü + 1"), ["2.5ü", "+", "1"]);
+
+        let tokens = tokenize("(1e-5 + y)");
+        assert_eq!(tokens[1].span, Span::new(1, 5));
+        assert_eq!(tokens[3].span, Span::new(8, 9));
+    }
+
+    #[test]
+    fn test_precedence() {
+        let cases = [
+            // Associativity
+            ("1 - 2 - 3", "(1 - 2) - 3"),
+            ("1 / 2 / 3", "(1 / 2) / 3"),
+            ("1 - 2 + 3", "(1 - 2) + 3"),
+            ("1 / 2 * 3", "(1 / 2) * 3"),
+            ("2 ^ 3 ^ 2", "2 ^ (3 ^ 2)"),
+            ("2 ** 3 ^ 2 ** 1", "2 ^ (3 ^ (2 ^ 1))"),
+            // Infix operators
+            ("1 + 2 * 3", "1 + (2 * 3)"),
+            ("1 * 2 + 3 / 4", "(1 * 2) + (3 / 4)"),
+            ("2 * 3 ^ 2", "2 * (3 ^ 2)"),
+            ("3 ^ 2 / 4", "(3 ^ 2) / 4"),
+            // Unary `+` and `-`
+            ("-x ^ 2", "-(x ^ 2)"),
+            ("-x * y", "(-x) * y"),
+            ("x * -y * z", "(x * (-y)) * z"),
+            ("x ^ -y ^ z", "x ^ (-(y ^ z))"),
+            ("x ^ -y * z", "(x ^ (-y)) * z"),
+            ("- - x + y", "(-(-x)) + y"),
+            ("+x - y", "(+x) - y"),
+            ("-(x) ^ 2", "-(x ^ 2)"),
+            ("x - -y", "x - (-y)"),
+            // Functions
+            ("sqrt 4 * 2", "(sqrt 4) * 2"),
+            ("3 + sqrt 4 * 2", "3 + ((sqrt 4) * 2)"),
+            ("sqrt 4 ^ 2", "(sqrt 4) ^ 2"),
+            ("sin x^2", "(sin x) ^ 2"),
+            ("sin x ** 2", "(sin x) ^ 2"),
+            ("sin(x^2)", "sin(x ^ 2)"),
+            ("sqrt -x ^ 2", "sqrt(-(x ^ 2))"),
+            ("sqrt -x * 2", "(sqrt(-x)) * 2"),
+            ("sin cos x", "sin(cos(x))"),
+            ("2 ^ sqrt x", "2 ^ (sqrt x)"),
+            ("sqrt(x + 1) ^ 2", "(sqrt(x + 1)) ^ 2"),
+            ("max(1, x) ^ 2", "(max(1, x)) ^ 2"),
+            // Argument lists
+            ("+(1, 2, 3) ^ 2", "(+(1, 2, 3)) ^ 2"),
+            ("-(8, 3) * 2", "(8 - 3) * 2"),
+            ("*(x, y) ^ 2", "(x * y) ^ 2"),
+            ("2 * +(x, y, z)", "2 * (+(x, y, z))"),
+            ("max(1, min(2, 3), -x ^ 2)", "max(1, min(2, 3), -(x ^ 2))"),
+        ];
+        for (input, expected) in cases.iter() {
+            assert_eq!(
+                ArithmeticExpression::parse(input).unwrap(),
+                ArithmeticExpression::parse(expected).unwrap(),
+                "{}",
+                input
+            );
+        }
+
+        let operands = |s| match ArithmeticExpression::parse(s).unwrap() {
+            ArithmeticExpression::Node { operands, .. } => operands,
+            expression => panic!("Unexpected expression: {:?}", expression),
+        };
+        assert_eq!(
+            operands("min(x, 2, y)"),
+            [
+                ArithmeticExpression::VariableLeaf("x".to_string()),
+                ArithmeticExpression::NumberLeaf(2.0),
+                ArithmeticExpression::VariableLeaf("y".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_depth() {
+        let nested = |n: usize| format!("{}x{}", "(".repeat(n), ")".repeat(n));
+        assert!(ArithmeticExpression::parse(&nested(MAX_DEPTH - 1)).is_ok());
+        assert_eq!(
+            ArithmeticExpression::parse(&nested(MAX_DEPTH)).unwrap_err(),
+            ParseError::TooDeep {
+                span: Span::new(MAX_DEPTH, MAX_DEPTH + 1)
+            }
+        );
+
+        let powers = |n: usize| format!("2{}", "^2".repeat(n));
+        assert!(ArithmeticExpression::parse(&powers(MAX_DEPTH - 1)).is_ok());
+        assert!(ArithmeticExpression::parse(&powers(MAX_DEPTH)).is_err());
+
+        let negations = |n: usize| format!("{}x", "-".repeat(n));
+        assert!(ArithmeticExpression::parse(&negations(MAX_DEPTH - 1)).is_ok());
+        assert!(ArithmeticExpression::parse(&negations(MAX_DEPTH)).is_err());
+
+        // Far deeper than the limit, which is reported without overflowing
+        let error = ArithmeticExpression::parse(&nested(100_000)).unwrap_err();
+        assert!(matches!(error, ParseError::TooDeep { .. }));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |s| ArithmeticExpression::parse(s).unwrap_err();
+
+        assert_eq!(
+            error("  "),
+            ParseError::EmptyExpression {
+                span: Span::new(0, 2)
+            }
+        );
+        assert_eq!(
+            error("+(1, ,2)"),
+            ParseError::EmptyExpression {
+                span: Span::new(4, 5)
+            }
+        );
+        assert_eq!(
+            error("3 + x)"),
+            ParseError::UnbalancedParenthesis {
+                span: Span::new(5, 6)
+            }
+        );
+        assert_eq!(
+            error("3 * * 4"),
+            ParseError::AdjacentOperators {
+                first: Operator::Star,
+                second: Operator::Star,
+                span: Span::new(2, 5)
+            }
+        );
+        assert_eq!(
+            error("2 + sqrt / 3"),
+            ParseError::AdjacentOperators {
+                first: Operator::Sqrt,
+                second: Operator::Slash,
+                span: Span::new(4, 10)
+            }
+        );
+        assert_eq!(
+            error("^(x) + 1"),
+            ParseError::WrongArity {
+                operator: Operator::Caret,
+                arguments: 1,
+                span: Span::new(0, 4)
+            }
+        );
+        assert_eq!(
+            error("3 + sqrt(4, x)"),
+            ParseError::WrongArity {
+                operator: Operator::Sqrt,
+                arguments: 2,
+                span: Span::new(4, 14)
+            }
+        );
+        assert_eq!(
+            error("2 * (x yy)"),
+            ParseError::AdjacentOperands {
+                span: Span::new(5, 9)
+            }
+        );
+        assert_eq!(
+            error("atan2(1) + 2"),
+            ParseError::WrongArity {
+                operator: Operator::Atan2,
+                arguments: 1,
+                span: Span::new(0, 8)
+            }
+        );
+        assert_eq!(
+            error("max()"),
+            ParseError::EmptyExpression {
+                span: Span::new(4, 4)
+            }
+        );
+        assert_eq!(
+            error("x -"),
+            ParseError::TrailingOperator {
+                operator: Operator::Minus,
+                span: Span::new(2, 3)
+            }
+        );
+        assert_eq!(
+            error("(1, 2) * 3"),
+            ParseError::UnexpectedToken {
+                token: ",".to_string(),
+                span: Span::new(2, 3)
+            }
+        );
+        assert_eq!(
+            error("sin x y"),
+            ParseError::AdjacentOperands {
+                span: Span::new(4, 7)
+            }
+        );
+        assert_eq!(
+            error("2 (x + 1)"),
+            ParseError::AdjacentOperands {
+                span: Span::new(0, 9)
+            }
+        );
+        assert_eq!(
+            error("1, 2"),
+            ParseError::UnexpectedToken {
+                token: ",".to_string(),
+                span: Span::new(1, 2)
+            }
+        );
+    }
+}
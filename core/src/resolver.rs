@@ -0,0 +1,149 @@
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+
+/// A source of values for the variables of an expression.
+///
+/// It is implemented for maps from names to values (`HashMap` and
+/// `BTreeMap`, with `String` or `&str` keys), for slices and arrays of
+/// `(name, value)` pairs, where the first pair with a name wins, and for
+/// closures.
+///
+/// ## Example:
+/// ```
+/// use arithmetic_parser::{ArithmeticExpression, VariableResolver};
+/// let expression = ArithmeticExpression::parse("(x+y)/(x-y)").unwrap();
+/// assert_eq!(1.5, expression.evaluate(&[("x", 5_f64), ("y", 1_f64)]).unwrap());
+///
+/// let resolver = |name: &str| match name {
+///     "x" => Some(5_f64),
+///     "y" => Some(1_f64),
+///     _ => None,
+/// };
+/// assert_eq!(1.5, expression.evaluate(&resolver).unwrap());
+///
+/// struct Point {
+///     x: f64,
+///     y: f64,
+/// }
+///
+/// impl VariableResolver for Point {
+///     fn resolve(&self, name: &str) -> Option<f64> {
+///         match name {
+///             "x" => Some(self.x),
+///             "y" => Some(self.y),
+///             _ => None,
+///         }
+///     }
+/// }
+/// assert_eq!(1.5, expression.evaluate(&Point { x: 5.0, y: 1.0 }).unwrap());
+/// ```
+pub trait VariableResolver {
+    /// The value of the variable `name`, or `None` if it has no value.
+    fn resolve(&self, name: &str) -> Option<f64>;
+}
+
+impl<K, S> VariableResolver for HashMap<K, f64, S>
+where
+    K: Borrow<str> + Hash + Eq,
+    S: BuildHasher,
+{
+    fn resolve(&self, name: &str) -> Option<f64> {
+        self.get(name).cloned()
+    }
+}
+
+impl<K: Borrow<str> + Ord> VariableResolver for BTreeMap<K, f64> {
+    fn resolve(&self, name: &str) -> Option<f64> {
+        self.get(name).cloned()
+    }
+}
+
+impl<K: AsRef<str>> VariableResolver for [(K, f64)] {
+    fn resolve(&self, name: &str) -> Option<f64> {
+        self.iter()
+            .find(|(key, _)| key.as_ref() == name)
+            .map(|(_, value)| *value)
+    }
+}
+
+impl<K: AsRef<str>, const N: usize> VariableResolver for [(K, f64); N] {
+    fn resolve(&self, name: &str) -> Option<f64> {
+        self[..].resolve(name)
+    }
+}
+
+impl<F: Fn(&str) -> Option<f64>> VariableResolver for F {
+    fn resolve(&self, name: &str) -> Option<f64> {
+        self(name)
+    }
+}
+
+/// A type which can be the value of a variable: the type of the fields of
+/// structs which derive `Variables` (see the `derive` feature).
+///
+/// It is implemented for `f64` and for the numeric types which can be
+/// converted to `f64` without loss. `None` is a missing variable.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` cannot be the value of a variable",
+    label = "not a type which can be converted to `f64` without loss",
+    note = "use `#[var(skip)]` to ignore the field"
+)]
+pub trait VariableValue {
+    fn value(&self) -> Option<f64>;
+}
+
+macro_rules! impl_variable_value {
+    ($($t:ty),*) => {
+        $(
+            impl VariableValue for $t {
+                fn value(&self) -> Option<f64> {
+                    Some(f64::from(*self))
+                }
+            }
+        )*
+    };
+}
+
+impl_variable_value!(f64, f32, i8, i16, i32, u8, u16, u32);
+
+impl<T: VariableValue> VariableValue for Option<T> {
+    fn value(&self) -> Option<f64> {
+        self.as_ref().and_then(VariableValue::value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolvers() {
+        fn check<R: VariableResolver + ?Sized>(resolver: &R) {
+            assert_eq!(resolver.resolve("x"), Some(1_f64));
+            assert_eq!(resolver.resolve("y"), Some(2_f64));
+            assert_eq!(resolver.resolve("z"), None);
+        }
+
+        let pairs = [("x", 1_f64), ("y", 2_f64), ("x", 3_f64)];
+        check(&pairs);
+        check(&pairs[..]);
+        let owned: Vec<(String, f64)> = pairs.iter().map(|(k, v)| (k.to_string(), *v)).collect();
+        check(&owned[..]);
+
+        let map: HashMap<&str, f64> = pairs[..2].iter().cloned().collect();
+        check(&map);
+        let map: HashMap<String, f64> = owned[..2].iter().cloned().collect();
+        check(&map);
+        let map: BTreeMap<&str, f64> = pairs[..2].iter().cloned().collect();
+        check(&map);
+        let map: BTreeMap<String, f64> = owned[..2].iter().cloned().collect();
+        check(&map);
+
+        check(&|name: &str| match name {
+            "x" => Some(1_f64),
+            "y" => Some(2_f64),
+            _ => None,
+        });
+    }
+}
@@ -0,0 +1,495 @@
+//! Serialization of expression trees, enabled by the `serde` feature.
+//!
+//! An [`ArithmeticExpression`] is serialized as a map with the version of
+//! the format and the tree:
+//! ```json
+//! {"version": 1, "expr": {"op": "+", "args": [{"var": "x"}, {"num": 2}]}}
+//! ```
+//! Each node of the tree is a map with one of the following shapes:
+//! - `{"num": 2.5}`: a number.
+//! - `{"var": "x"}`: a variable.
+//! - `{"const": "pi", "value": 3.141592653589793}`: a constant, with the
+//!   value it had when the expression was parsed.
+//! - `{"op": "+", "args": [...]}`: an operator applied to its operands, in
+//!   order. The operator is identified by the name used in the input (`+`,
+//!   `-`, `*`, `/`, `^` or `**`, `sqrt`, `min`, ...), never by its position
+//!   in [`Operator`], so adding operators does not change old payloads.
+//!
+//! Numbers which are not finite are written as the strings `"nan"`, `"inf"`
+//! and `"-inf"`, since formats such as JSON cannot represent them.
+//!
+//! A payload is read if its version is at most [`SERIALIZATION_VERSION`].
+//! Unknown keys are ignored, but a key which appears twice in the same map
+//! is an error. Functions registered in a [`Context`] are written by name,
+//! since their code is not serialized: they can only be read back with a
+//! [`ContextSeed`] for a context where they are registered.
+//!
+//! A [`Program`](crate::Program) is serialized with the default
+//! representation of serde, and its numbers are written like above.
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+
+use super::context::Context;
+use super::data_structures::{ArithmeticExpression, Operator};
+use super::parser::{try_parse_operator, DEFAULT_CONTEXT};
+
+/// The version of the serialization format written by this crate.
+pub const SERIALIZATION_VERSION: u32 = 1;
+
+impl Serialize for Operator {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Operator {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Operator, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        find_operator(&name, &DEFAULT_CONTEXT)
+    }
+}
+
+fn find_operator<E: de::Error>(name: &str, context: &Context) -> Result<Operator, E> {
+    try_parse_operator(name, context)
+        .ok_or_else(|| de::Error::custom(format_args!("unknown operator `{}`", name)))
+}
+
+impl Serialize for ArithmeticExpression {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("version", &SERIALIZATION_VERSION)?;
+        map.serialize_entry("expr", &Tree(self))?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for ArithmeticExpression {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<ArithmeticExpression, D::Error> {
+        ContextSeed(&DEFAULT_CONTEXT).deserialize(deserializer)
+    }
+}
+
+/// Deserialize an [`ArithmeticExpression`] which can call the functions
+/// registered in a [`Context`], with [`DeserializeSeed::deserialize`].
+/// Deserializing an expression directly is the same as using the default
+/// context.
+#[derive(Debug, Clone, Copy)]
+pub struct ContextSeed<'a>(pub &'a Context);
+
+impl<'de> DeserializeSeed<'de> for ContextSeed<'_> {
+    type Value = ArithmeticExpression;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<ArithmeticExpression, D::Error> {
+        deserializer.deserialize_map(EnvelopeVisitor(self.0))
+    }
+}
+
+/// A node of the tree, without the version.
+struct Tree<'a>(&'a ArithmeticExpression);
+
+/// Deserialize a node of the tree, without the version.
+#[derive(Clone, Copy)]
+struct TreeSeed<'a>(&'a Context);
+
+/// Deserialize the operands of a node.
+struct OperandsSeed<'a>(&'a Context);
+
+/// A number which can be serialized even if it is not finite.
+struct Number(f64);
+
+impl Serialize for Tree<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            ArithmeticExpression::NumberLeaf(n) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("num", &Number(*n))?;
+                map.end()
+            }
+            ArithmeticExpression::VariableLeaf(name) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("var", name)?;
+                map.end()
+            }
+            ArithmeticExpression::Constant { name, value } => {
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry("const", name)?;
+                map.serialize_entry("value", &Number(*value))?;
+                map.end()
+            }
+            ArithmeticExpression::Node { node, operands } => {
+                let operands: Vec<_> = operands.iter().map(Tree).collect();
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry("op", node)?;
+                map.serialize_entry("args", &operands)?;
+                map.end()
+            }
+        }
+    }
+}
+
+impl Serialize for Number {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.0.is_nan() {
+            serializer.serialize_str("nan")
+        } else if self.0 == f64::INFINITY {
+            serializer.serialize_str("inf")
+        } else if self.0 == f64::NEG_INFINITY {
+            serializer.serialize_str("-inf")
+        } else {
+            serializer.serialize_f64(self.0)
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for TreeSeed<'_> {
+    type Value = ArithmeticExpression;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<ArithmeticExpression, D::Error> {
+        deserializer.deserialize_map(TreeVisitor(self.0))
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for OperandsSeed<'_> {
+    type Value = Vec<ArithmeticExpression>;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Vec<ArithmeticExpression>, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for OperandsSeed<'_> {
+    type Value = Vec<ArithmeticExpression>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a sequence of nodes")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut operands = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(operand) = seq.next_element_seed(TreeSeed(self.0))? {
+            operands.push(operand);
+        }
+        Ok(operands)
+    }
+}
+
+/// Read the value of `key` into `slot`, failing if the key was already read.
+fn read_value<'de, A, S>(
+    map: &mut A,
+    slot: &mut Option<S::Value>,
+    key: &'static str,
+    seed: S,
+) -> Result<(), A::Error>
+where
+    A: MapAccess<'de>,
+    S: DeserializeSeed<'de>,
+{
+    if slot.is_some() {
+        return Err(de::Error::duplicate_field(key));
+    }
+    *slot = Some(map.next_value_seed(seed)?);
+    Ok(())
+}
+
+impl<'de> Deserialize<'de> for Number {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Number, D::Error> {
+        deserializer.deserialize_any(NumberVisitor)
+    }
+}
+
+/// Serialize a number like the numbers of a tree, for `#[serde(with)]`.
+pub(crate) mod number {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(n: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        Number(*n).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        Number::deserialize(deserializer).map(|n| n.0)
+    }
+}
+
+struct EnvelopeVisitor<'a>(&'a Context);
+
+impl<'de> Visitor<'de> for EnvelopeVisitor<'_> {
+    type Value = ArithmeticExpression;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a map with the keys `version` and `expr`")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut version: Option<u32> = None;
+        let mut expression = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "version" => read_value(&mut map, &mut version, "version", PhantomData)?,
+                "expr" => read_value(&mut map, &mut expression, "expr", TreeSeed(self.0))?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        match (version, expression) {
+            (None, _) => Err(de::Error::missing_field("version")),
+            (Some(version), _) if version == 0 || version > SERIALIZATION_VERSION => Err(
+                de::Error::custom(format_args!("unsupported version {}", version)),
+            ),
+            (_, None) => Err(de::Error::missing_field("expr")),
+            (_, Some(expression)) => Ok(expression),
+        }
+    }
+}
+
+struct TreeVisitor<'a>(&'a Context);
+
+impl<'de> Visitor<'de> for TreeVisitor<'_> {
+    type Value = ArithmeticExpression;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "a map with one of the keys `num`, `var`, `const` or `op`"
+        )
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut number: Option<Number> = None;
+        let mut variable: Option<String> = None;
+        let mut constant: Option<String> = None;
+        let mut value: Option<Number> = None;
+        let mut operator: Option<String> = None;
+        let mut operands = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "num" => read_value(&mut map, &mut number, "num", PhantomData)?,
+                "var" => read_value(&mut map, &mut variable, "var", PhantomData)?,
+                "const" => read_value(&mut map, &mut constant, "const", PhantomData)?,
+                "value" => read_value(&mut map, &mut value, "value", PhantomData)?,
+                "op" => read_value(&mut map, &mut operator, "op", PhantomData)?,
+                "args" => read_value(&mut map, &mut operands, "args", OperandsSeed(self.0))?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        let expression = match (number, variable, constant, operator) {
+            (Some(number), None, None, None) => ArithmeticExpression::NumberLeaf(number.0),
+            (None, Some(name), None, None) => ArithmeticExpression::VariableLeaf(name),
+            (None, None, Some(name), None) => ArithmeticExpression::Constant {
+                name,
+                value: value.ok_or_else(|| de::Error::missing_field("value"))?.0,
+            },
+            (None, None, None, Some(operator)) => {
+                let operator = find_operator(&operator, self.0)?;
+                let operands = operands.ok_or_else(|| de::Error::missing_field("args"))?;
+                if !operator.is_nary(operands.len()) {
+                    return Err(de::Error::custom(format_args!(
+                        "`{}` does not accept {} arguments",
+                        operator.as_str(),
+                        operands.len()
+                    )));
+                }
+                ArithmeticExpression::Node {
+                    node: operator,
+                    operands,
+                }
+            }
+            _ => return Err(de::Error::custom(ExpectingTree)),
+        };
+        Ok(expression)
+    }
+}
+
+/// The error for a node which does not have exactly one of the keys.
+struct ExpectingTree;
+
+impl fmt::Display for ExpectingTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        TreeVisitor(&DEFAULT_CONTEXT).expecting(f)
+    }
+}
+
+struct NumberVisitor;
+
+impl<'de> Visitor<'de> for NumberVisitor {
+    type Value = Number;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a number, `nan`, `inf` or `-inf`")
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Number, E> {
+        Ok(Number(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Number, E> {
+        Ok(Number(v as f64))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Number, E> {
+        Ok(Number(v as f64))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Number, E> {
+        match v {
+            "nan" => Ok(Number(f64::NAN)),
+            "inf" => Ok(Number(f64::INFINITY)),
+            "-inf" => Ok(Number(f64::NEG_INFINITY)),
+            _ => Err(de::Error::invalid_value(de::Unexpected::Str(v), &self)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arity, Context};
+
+    fn parse(s: &str) -> ArithmeticExpression {
+        ArithmeticExpression::parse(s).unwrap()
+    }
+
+    fn from_json(s: &str) -> Result<ArithmeticExpression, String> {
+        serde_json::from_str(s).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_schema() {
+        assert_eq!(
+            serde_json::to_string(&parse("-x + 2.5 * pi")).unwrap(),
+            r#"{"version":1,"expr":{"op":"+","args":[{"op":"-","args":[{"var":"x"}]},{"op":"*","args":[{"num":2.5},{"const":"pi","value":3.141592653589793}]}]}}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&parse("1e400 - nan")).unwrap(),
+            r#"{"version":1,"expr":{"op":"-","args":[{"num":"inf"},{"const":"nan","value":"nan"}]}}"#
+        );
+        assert_eq!(
+            from_json(
+                r#"{"expr": {"op": "max", "args": [{"num": 1}, {"var": "y"}]}, "version": 1, "note": "ignored"}"#
+            ),
+            Ok(parse("max(1, y)"))
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let inputs = [
+            "x",
+            "(x+y)/(x-y)",
+            "2^3^2 - -x",
+            "+(1, 2, 3) * sqrt(x)",
+            "log(2, x) + atan2(y, x) - min(1, 2, 3, 4)",
+            "0.1 + 6.674e-11 * tau - nan + 1e400",
+        ];
+        for input in inputs.iter() {
+            let expression = parse(input);
+
+            let json = serde_json::to_string(&expression).unwrap();
+            assert_eq!(from_json(&json), Ok(expression.clone()), "{}", input);
+
+            let bytes = rmp_serde::to_vec(&expression).unwrap();
+            let decoded: ArithmeticExpression = rmp_serde::from_slice(&bytes).unwrap();
+            assert_eq!(decoded, expression, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |s| from_json(s).unwrap_err();
+        assert!(error(r#"{"version": 2, "expr": {"var": "x"}}"#).contains("unsupported version 2"));
+        assert!(error(r#"{"expr": {"var": "x"}}"#).contains("missing field `version`"));
+        assert!(error(r#"{"version": 1}"#).contains("missing field `expr`"));
+        assert!(
+            error(r#"{"version": 1, "expr": {"op": "erf", "args": [{"num": 1}]}}"#)
+                .contains("unknown operator `erf`")
+        );
+        assert!(
+            error(r#"{"version": 1, "expr": {"op": "*", "args": [{"num": 1}]}}"#)
+                .contains("`*` does not accept 1 arguments")
+        );
+        assert!(
+            error(r#"{"version": 1, "expr": {"var": "x", "num": 1}}"#).contains("one of the keys")
+        );
+        assert!(
+            error(r#"{"version": 1, "expr": {"const": "pi"}}"#).contains("missing field `value`")
+        );
+        assert!(error(r#"{"version": 1, "expr": {"num": "infinity"}}"#)
+            .contains("a number, `nan`, `inf` or `-inf`"));
+
+        let mut context = Context::new();
+        context.register_function("f", Arity::Exactly(1), |args: &[f64]| {
+            Ok::<_, String>(args[0])
+        });
+        let expression = ArithmeticExpression::parse_with_context("f(x)", &context).unwrap();
+        let json = serde_json::to_string(&expression).unwrap();
+        assert_eq!(
+            json,
+            r#"{"version":1,"expr":{"op":"f","args":[{"var":"x"}]}}"#
+        );
+        assert!(from_json(&json)
+            .unwrap_err()
+            .contains("unknown operator `f`"));
+
+        assert!(
+            error(r#"{"version": 1, "version": 1, "expr": {"var": "x"}}"#)
+                .contains("duplicate field `version`")
+        );
+        assert!(error(r#"{"version": 1, "expr": {"var": "x", "var": "y"}}"#)
+            .contains("duplicate field `var`"));
+        assert!(
+            error(r#"{"version": 1, "expr": {"op": "-", "args": [], "args": [{"num": 1}]}}"#)
+                .contains("duplicate field `args`")
+        );
+    }
+
+    #[test]
+    fn test_context_seed() {
+        let mut context = Context::new();
+        context.register_function("f", Arity::Exactly(1), |args: &[f64]| {
+            Ok::<_, String>(args[0])
+        });
+        let expression =
+            ArithmeticExpression::parse_with_context("f(x) + sqrt(f 2)", &context).unwrap();
+        let json = serde_json::to_string(&expression).unwrap();
+        let mut deserializer = serde_json::Deserializer::from_str(&json);
+        let decoded = ContextSeed(&context)
+            .deserialize(&mut deserializer)
+            .unwrap();
+        assert_eq!(decoded, expression);
+
+        let mut deserializer = serde_json::Deserializer::from_str(&json);
+        let error = ContextSeed(&Context::new())
+            .deserialize(&mut deserializer)
+            .unwrap_err();
+        assert!(error.to_string().contains("unknown operator `f`"));
+    }
+
+    #[test]
+    fn test_double_star() {
+        assert_eq!(
+            from_json(
+                r#"{"version": 1, "expr": {"op": "**", "args": [{"var": "x"}, {"num": 2}]}}"#
+            ),
+            Ok(parse("x ^ 2"))
+        );
+    }
+}
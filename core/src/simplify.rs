@@ -0,0 +1,277 @@
+use super::data_structures::{ArithmeticExpression, Operator};
+use super::options::SimplifyOptions;
+
+impl ArithmeticExpression {
+    /// Rewrite the expression with a few simple rules, applied bottom-up:
+    /// - Operators (except functions registered in a
+    ///   [`Context`](crate::Context)) whose operands are all numbers are
+    ///   replaced by their result, if it is finite.
+    /// - `x + -0`, `x - 0`, `x * 1`, `x / 1` and `x ^ 1` become `x`, `x ^ 0`
+    ///   becomes `1`, `-0 - x` becomes `-x` and `--x` becomes `x`.
+    /// - A `+` whose first operand is a `+` is flattened: e.g., `x + y + z`,
+    ///   which is `+(+(x, y), z)`, becomes `+(x, y, z)`.
+    ///
+    /// The result evaluates to the same value as the expression. See
+    /// [`SimplifyOptions`] for rules which do not preserve the value, such
+    /// as removing `+ 0`, which changes the sign of a zero result.
+    ///
+    /// ## Example:
+    /// ```
+    /// use arithmetic_parser::ArithmeticExpression;
+    /// let expression = ArithmeticExpression::parse("x*1 - 0 + (2*3)").unwrap();
+    /// assert_eq!("x + 6", expression.simplify().to_string());
+    /// ```
+    pub fn simplify(&self) -> ArithmeticExpression {
+        self.simplify_with(&SimplifyOptions::default())
+    }
+
+    /// Rewrite the expression like [`ArithmeticExpression::simplify`], with
+    /// custom options.
+    pub fn simplify_with(&self, options: &SimplifyOptions) -> ArithmeticExpression {
+        simplify(self.clone(), options)
+    }
+}
+
+pub(crate) fn simplify(
+    expression: ArithmeticExpression,
+    options: &SimplifyOptions,
+) -> ArithmeticExpression {
+    match expression {
+        ArithmeticExpression::Node { node, operands } => {
+            let operands = operands
+                .into_iter()
+                .map(|operand| simplify(operand, options))
+                .collect();
+            simplify_node(node, operands, options)
+        }
+        leaf => leaf,
+    }
+}
+
+fn simplify_node(
+    node: Operator,
+    mut operands: Vec<ArithmeticExpression>,
+    options: &SimplifyOptions,
+) -> ArithmeticExpression {
+    if !matches!(node, Operator::Custom(_)) {
+        let values: Option<Vec<f64>> = operands.iter().map(as_number).collect();
+        if let Some(Ok(value)) = values.map(|values| node.apply(&values)) {
+            if value.is_finite() {
+                return ArithmeticExpression::NumberLeaf(value);
+            }
+        }
+    }
+
+    let is = |i: usize, n: f64| as_number(&operands[i]) == Some(n);
+    // Adding `-0` and subtracting `0` never change a value, and neither
+    // does `-0 - x` compared to `-x`. The other zero does not have the
+    // same property for `-0`
+    let is_removable_zero = |n: Option<f64>, sign_negative: bool| {
+        n == Some(0.0)
+            && (options.ignore_zero_sign || n.unwrap().is_sign_negative() == sign_negative)
+    };
+    match (&node, operands.len()) {
+        (Operator::Plus, _) => {
+            operands.retain(|operand| !is_removable_zero(as_number(operand), true));
+            let flatten = if options.reassociate {
+                operands.len()
+            } else {
+                operands.len().min(1)
+            };
+            let mut flattened = Vec::with_capacity(operands.len());
+            for (i, operand) in operands.into_iter().enumerate() {
+                match operand {
+                    ArithmeticExpression::Node {
+                        node: Operator::Plus,
+                        operands: inner,
+                    } if i < flatten => flattened.extend(inner),
+                    operand => flattened.push(operand),
+                }
+            }
+            match flattened.len() {
+                0 => ArithmeticExpression::NumberLeaf(0.0),
+                1 => flattened.pop().unwrap(),
+                _ => ArithmeticExpression::Node {
+                    node,
+                    operands: flattened,
+                },
+            }
+        }
+        (Operator::Minus, 2) if is_removable_zero(as_number(&operands[1]), false) => {
+            operands.swap_remove(0)
+        }
+        (Operator::Minus, 2) if is_removable_zero(as_number(&operands[0]), true) => {
+            simplify_node(node, vec![operands.pop().unwrap()], options)
+        }
+        (Operator::Minus, 1) => match operands.pop().unwrap() {
+            ArithmeticExpression::Node {
+                node: Operator::Minus,
+                operands: mut inner,
+            } if inner.len() == 1 => inner.pop().unwrap(),
+            operand => ArithmeticExpression::Node {
+                node,
+                operands: vec![operand],
+            },
+        },
+        (Operator::Star, 2) if options.annihilate_zero && (is(0, 0.0) || is(1, 0.0)) => {
+            ArithmeticExpression::NumberLeaf(0.0)
+        }
+        (Operator::Star, 2) if is(0, 1.0) => operands.swap_remove(1),
+        (Operator::Star, 2) | (Operator::Slash, 2) | (Operator::Caret, 2) if is(1, 1.0) => {
+            operands.swap_remove(0)
+        }
+        (Operator::Slash, 2) if options.annihilate_zero && is(0, 0.0) => {
+            ArithmeticExpression::NumberLeaf(0.0)
+        }
+        (Operator::Caret, 2) if is(1, 0.0) => ArithmeticExpression::NumberLeaf(1.0),
+        _ => ArithmeticExpression::Node { node, operands },
+    }
+}
+
+fn as_number(expression: &ArithmeticExpression) -> Option<f64> {
+    match expression {
+        ArithmeticExpression::NumberLeaf(n) => Some(*n),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::HashMap;
+
+    fn simplified(s: &str, options: &SimplifyOptions) -> String {
+        let expression = ArithmeticExpression::parse(s).unwrap();
+        expression.simplify_with(options).to_string()
+    }
+
+    #[test]
+    fn test_simplify() {
+        let cases = [
+            ("1*x + x*1", "x + x"),
+            ("(x + 0) * (0 + y) - 0", "(x + 0) * (0 + y)"),
+            ("(x + -0) * (-0 + y)", "x * y"),
+            ("0 - x", "0 - x"),
+            ("-0 - x", "-x"),
+            ("x - -0", "x - (-0)"),
+            ("1 / (x + 0)", "1 / (x + 0)"),
+            ("- - - x", "-x"),
+            ("x * 0 + 0 / y + z ^ 0", "+(x * 0, 0 / y, 1)"),
+            ("x / 1 + x ^ 1", "x + x"),
+            ("2 * 3 + x * (4 - 1)", "6 + x * 3"),
+            ("sqrt(4) * pi", "2 * pi"),
+            ("+(-0, x, -0, y)", "x + y"),
+            ("1 / 0 + sqrt(-1)", "1 / 0 + sqrt(-1)"),
+            ("x*1 - 0 + (2*3)", "x + 6"),
+            ("x + y + (z - 0) + w", "+(x, y, z, w)"),
+            ("x + (y + z)", "x + (y + z)"),
+            ("+(x + y, z, +(w, 1))", "+(x, y, z, w + 1)"),
+        ];
+        for (input, expected) in cases.iter() {
+            assert_eq!(
+                simplified(input, &SimplifyOptions::default()),
+                *expected,
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_simplify_options() {
+        let options = SimplifyOptions {
+            annihilate_zero: true,
+            ignore_zero_sign: true,
+            ..SimplifyOptions::default()
+        };
+        assert_eq!(simplified("x * 0 + 0 / y + z ^ 0", &options), "1");
+        assert_eq!(simplified("0 * (x + y) - z", &options), "-z");
+        assert_eq!(simplified("x + (y + z)", &options), "x + (y + z)");
+
+        let options = SimplifyOptions {
+            ignore_zero_sign: true,
+            ..SimplifyOptions::default()
+        };
+        assert_eq!(simplified("(x + 0) * (0 + y) - 0", &options), "x * y");
+        assert_eq!(simplified("0 - x", &options), "-x");
+        assert_eq!(simplified("0 - -x", &options), "x");
+        assert_eq!(simplified("+(0, x, 0, y)", &options), "x + y");
+
+        let options = SimplifyOptions {
+            reassociate: true,
+            ..SimplifyOptions::default()
+        };
+        assert_eq!(simplified("x * 0", &options), "x * 0");
+        assert_eq!(simplified("x + (y + z)", &options), "+(x, y, z)");
+        assert_eq!(
+            simplified("+(x + y, z, +(w, 1))", &options),
+            "+(x, y, z, w, 1)"
+        );
+    }
+
+    #[test]
+    fn test_simplify_zero_sign() {
+        let expression = ArithmeticExpression::parse("1 / (x + 0)").unwrap();
+        let variables = [("x", -0_f64)];
+        assert_eq!(
+            expression.simplify().evaluate(&variables),
+            Ok(f64::INFINITY)
+        );
+        let options = SimplifyOptions {
+            ignore_zero_sign: true,
+            ..SimplifyOptions::default()
+        };
+        assert_eq!(
+            expression.simplify_with(&options).evaluate(&variables),
+            Ok(f64::NEG_INFINITY)
+        );
+    }
+
+    fn expression_strategy() -> impl Strategy<Value = ArithmeticExpression> {
+        let leaf = prop_oneof![
+            prop::sample::select(vec!["x", "y"])
+                .prop_map(|x| ArithmeticExpression::VariableLeaf(x.to_string())),
+            prop::sample::select(vec![0.0, -0.0, 1.0, 2.0, 0.5, 3.0, 1e300])
+                .prop_map(ArithmeticExpression::NumberLeaf),
+        ];
+        leaf.prop_recursive(5, 40, 3, |inner| {
+            prop::collection::vec(inner, 1..=3)
+                .prop_flat_map(|operands| {
+                    let nodes: Vec<Operator> = Operator::get_all()
+                        .iter()
+                        .filter(|node| node.is_nary(operands.len()))
+                        .cloned()
+                        .collect();
+                    (prop::sample::select(nodes), Just(operands))
+                })
+                .prop_map(|(node, operands)| ArithmeticExpression::Node { node, operands })
+        })
+    }
+
+    proptest! {
+        /// The simplified expression must evaluate to the same value as the
+        /// original one.
+        #[test]
+        fn test_simplify_equivalence(
+            expression in expression_strategy(),
+            x in any::<f64>(),
+            y in any::<f64>(),
+        ) {
+            let variables: HashMap<_, _> = [("x", x), ("y", y)].iter().cloned().collect();
+            let simplified = expression.simplify();
+            let expected = expression.evaluate(&variables).unwrap();
+            let result = simplified.evaluate(&variables).unwrap();
+            // NaN payloads are not compared, and neither is the sign of a
+            // zero result, although the sign of intermediate zeros is
+            prop_assert!(
+                result == expected || (result.is_nan() && expected.is_nan()),
+                "{} is {}, but {} is {}",
+                expression,
+                expected,
+                simplified,
+                result
+            );
+        }
+    }
+}
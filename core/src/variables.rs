@@ -0,0 +1,145 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::data_structures::ArithmeticExpression;
+use super::error::EvalError;
+use super::resolver::VariableResolver;
+
+impl ArithmeticExpression {
+    /// The names of the variables of the expression, in alphabetical order.
+    ///
+    /// Constants (e.g., `pi`) are not variables, even though a value can be
+    /// provided for them (see [`ShadowingPolicy`](crate::ShadowingPolicy)).
+    ///
+    /// ## Example:
+    /// ```
+    /// use arithmetic_parser::ArithmeticExpression;
+    /// let expression = ArithmeticExpression::parse("y * x + 2 * pi * x").unwrap();
+    /// let variables: Vec<_> = expression.variables().into_iter().collect();
+    /// assert_eq!(vec!["x", "y"], variables);
+    /// ```
+    pub fn variables(&self) -> BTreeSet<&str> {
+        self.variable_occurrences().into_keys().collect()
+    }
+
+    /// The number of times each variable of the expression appears in it.
+    ///
+    /// ## Example:
+    /// ```
+    /// use arithmetic_parser::ArithmeticExpression;
+    /// let expression = ArithmeticExpression::parse("y * x + 2 * x").unwrap();
+    /// let occurrences = expression.variable_occurrences();
+    /// assert_eq!(2, occurrences["x"]);
+    /// assert_eq!(1, occurrences["y"]);
+    /// ```
+    pub fn variable_occurrences(&self) -> BTreeMap<&str, usize> {
+        let mut occurrences = BTreeMap::new();
+        count_occurrences(self, &mut occurrences);
+        occurrences
+    }
+
+    /// The names of the variables of the expression which do not have a
+    /// value in `provided`, in alphabetical order. They are the ones listed
+    /// by [`EvalError::MissingVariables`] if the expression is evaluated with
+    /// `provided`.
+    ///
+    /// ## Example:
+    /// ```
+    /// use arithmetic_parser::ArithmeticExpression;
+    /// let expression = ArithmeticExpression::parse("(x+y)/(x-z)").unwrap();
+    /// let variables = [("y", 1_f64)];
+    /// let missing: Vec<_> = expression.missing_variables(&variables).into_iter().collect();
+    /// assert_eq!(vec!["x", "z"], missing);
+    /// ```
+    pub fn missing_variables<R: VariableResolver + ?Sized>(&self, provided: &R) -> BTreeSet<&str> {
+        self.variables()
+            .into_iter()
+            .filter(|name| provided.resolve(name).is_none())
+            .collect()
+    }
+
+    /// Evaluators stop at the first variable without a value: replace the
+    /// [`EvalError::MissingVariables`] they return with one listing all the
+    /// variables for which `is_provided` is false.
+    pub(crate) fn report_missing_variables<F: Fn(&str) -> bool>(
+        &self,
+        error: EvalError,
+        is_provided: F,
+    ) -> EvalError {
+        match error {
+            EvalError::MissingVariables { .. } => EvalError::MissingVariables {
+                names: self
+                    .variables()
+                    .into_iter()
+                    .filter(|name| !is_provided(name))
+                    .map(str::to_string)
+                    .collect(),
+            },
+            error => error,
+        }
+    }
+}
+
+fn count_occurrences<'a>(
+    expression: &'a ArithmeticExpression,
+    occurrences: &mut BTreeMap<&'a str, usize>,
+) {
+    match expression {
+        ArithmeticExpression::VariableLeaf(x) => *occurrences.entry(x).or_insert(0) += 1,
+        ArithmeticExpression::Node { operands, .. } => {
+            for operand in operands {
+                count_occurrences(operand, occurrences);
+            }
+        }
+        ArithmeticExpression::NumberLeaf(_) | ArithmeticExpression::Constant { .. } => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variables() {
+        let expression = ArithmeticExpression::parse("x * (y + x) / e + sqrt(z^x) - 3").unwrap();
+        assert_eq!(
+            expression.variables(),
+            ["x", "y", "z"].iter().cloned().collect()
+        );
+        assert_eq!(
+            expression.variable_occurrences(),
+            [("x", 3), ("y", 1), ("z", 1)].iter().cloned().collect()
+        );
+        assert!(ArithmeticExpression::parse("2 * pi")
+            .unwrap()
+            .variables()
+            .is_empty());
+
+        let variables = [("y", 1_f64), ("w", 2_f64), ("e", 3_f64)];
+        assert_eq!(
+            expression.missing_variables(&variables),
+            ["x", "z"].iter().cloned().collect()
+        );
+    }
+
+    #[test]
+    fn test_missing_variables_error() {
+        let expression = ArithmeticExpression::parse("(x + y) * z + y / w").unwrap();
+        let variables = [("y", 1_f64)];
+        let error = EvalError::MissingVariables {
+            names: vec!["w".to_string(), "x".to_string(), "z".to_string()],
+        };
+
+        assert_eq!(expression.evaluate(&variables), Err(error.clone()));
+        assert_eq!(
+            expression.evaluate_with_gradient(&variables),
+            Err(error.clone())
+        );
+        assert_eq!(expression.compile(&["y"]).unwrap_err(), error);
+        assert_eq!(expression.to_program(&["y"]).unwrap_err(), error);
+
+        let y = [1_f64];
+        let columns = [("y", &y[..])].iter().cloned().collect();
+        let mut out = [0_f64];
+        assert_eq!(expression.evaluate_batch(&columns, &mut out), Err(error));
+    }
+}
@@ -0,0 +1,433 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::compiled::variable_positions;
+use super::data_structures::{ArithmeticExpression, Operator};
+use super::error::EvalError;
+use super::options::{ArithmeticMode, EvaluationOptions, ShadowingPolicy};
+
+/// An instruction of a [`Program`]. Instructions take their arguments from
+/// the top of the value stack and push their result on it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Instruction {
+    PushConst(#[cfg_attr(feature = "serde", serde(with = "super::serialization::number"))] f64),
+    /// Push the value of the variable at this position.
+    PushVar(usize),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    /// Binary `^`.
+    Pow,
+    /// Unary `-`.
+    Neg,
+    /// Apply an operator to the given number of arguments.
+    CallN(Operator, usize),
+}
+
+/// An arithmetic expression lowered to a flat sequence of instructions, to be
+/// run by a [`Machine`].
+///
+/// It is produced by [`ArithmeticExpression::to_program`]. With the `serde`
+/// feature, a program can be serialized, e.g., to cache it. Functions
+/// registered in a [`Context`](crate::Context) cannot be deserialized.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Program {
+    instructions: Vec<Instruction>,
+    variables: usize,
+    max_stack: usize,
+    arithmetic: ArithmeticMode,
+}
+
+/// A stack machine which runs [`Program`]s. It keeps its value stack between
+/// runs, so that it is only allocated once.
+///
+/// ## Example:
+/// ```
+/// use arithmetic_parser::{ArithmeticExpression, Machine};
+/// let program = ArithmeticExpression::parse("(x+y)/(x-y)")
+///     .unwrap()
+///     .to_program(&["x", "y"])
+///     .unwrap();
+/// let mut machine = Machine::new();
+/// assert_eq!(1.5, machine.run(&program, &[5_f64, 1_f64]).unwrap());
+/// assert_eq!(3_f64, machine.run(&program, &[2_f64, 1_f64]).unwrap());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Machine {
+    stack: Vec<f64>,
+}
+
+/// A step of the traversal of a tree which lowers it to a program.
+enum Visit<'a> {
+    Enter(&'a ArithmeticExpression),
+    Exit(&'a Operator, usize),
+}
+
+impl ArithmeticExpression {
+    /// Lower the expression to a [`Program`] for the variables in
+    /// `variables`, with the default [`EvaluationOptions`]. The value of each
+    /// variable must be passed to [`Machine::run`] at the same position.
+    ///
+    /// Lowering the expression and running the program are not recursive,
+    /// so neither of them overflows the stack on a deeply nested
+    /// expression. This only holds for these two steps: formatting,
+    /// dropping or evaluating such an expression can still overflow it.
+    ///
+    /// ## Errors:
+    /// The same as [`ArithmeticExpression::compile`].
+    pub fn to_program(&self, variables: &[&str]) -> Result<Program, EvalError> {
+        self.to_program_with(variables, &EvaluationOptions::default())
+    }
+
+    /// Lower the expression like [`ArithmeticExpression::to_program`], with
+    /// custom options.
+    pub fn to_program_with(
+        &self,
+        variables: &[&str],
+        options: &EvaluationOptions,
+    ) -> Result<Program, EvalError> {
+        let positions = variable_positions(variables);
+
+        let mut instructions = Vec::new();
+        let mut stack_size = 0;
+        let mut max_stack = 0;
+        let mut pending = vec![Visit::Enter(self)];
+        while let Some(visit) = pending.pop() {
+            let instruction = match visit {
+                Visit::Enter(ArithmeticExpression::NumberLeaf(n)) => Instruction::PushConst(*n),
+                Visit::Enter(ArithmeticExpression::VariableLeaf(x)) => {
+                    match positions.get(x.as_str()) {
+                        Some(i) => Instruction::PushVar(*i),
+                        None => {
+                            let error = EvalError::MissingVariables {
+                                names: vec![x.clone()],
+                            };
+                            return Err(self.report_missing_variables(error, |name| {
+                                positions.contains_key(name)
+                            }));
+                        }
+                    }
+                }
+                Visit::Enter(ArithmeticExpression::Constant { name, value }) => {
+                    match (positions.get(name.as_str()), options.shadowing) {
+                        (None, _) | (Some(_), ShadowingPolicy::PreferConstant) => {
+                            Instruction::PushConst(*value)
+                        }
+                        (Some(i), ShadowingPolicy::PreferVariable) => Instruction::PushVar(*i),
+                        (Some(_), ShadowingPolicy::Error) => {
+                            return Err(EvalError::ShadowedConstant { name: name.clone() })
+                        }
+                    }
+                }
+                Visit::Enter(ArithmeticExpression::Node { node, operands }) => {
+                    if !node.is_nary(operands.len()) {
+                        return Err(EvalError::WrongArity {
+                            operator: node.clone(),
+                            arguments: operands.len(),
+                        });
+                    }
+                    pending.push(Visit::Exit(node, operands.len()));
+                    pending.extend(operands.iter().rev().map(Visit::Enter));
+                    continue;
+                }
+                Visit::Exit(operator, arguments) => {
+                    stack_size -= arguments;
+                    match (operator, arguments) {
+                        (Operator::Plus, 2) => Instruction::Add,
+                        (Operator::Minus, 2) => Instruction::Sub,
+                        (Operator::Star, 2) => Instruction::Mul,
+                        (Operator::Slash, 2) => Instruction::Div,
+                        (Operator::Caret, 2) => Instruction::Pow,
+                        (Operator::Minus, 1) => Instruction::Neg,
+                        _ => Instruction::CallN(operator.clone(), arguments),
+                    }
+                }
+            };
+            stack_size += 1;
+            max_stack = max_stack.max(stack_size);
+            instructions.push(instruction);
+        }
+
+        Ok(Program {
+            instructions,
+            variables: variables.len(),
+            max_stack,
+            arithmetic: options.arithmetic,
+        })
+    }
+}
+
+impl Instruction {
+    /// The operator applied by the instruction, if any.
+    fn operator(&self) -> Option<Operator> {
+        match self {
+            Instruction::PushConst(_) | Instruction::PushVar(_) => None,
+            Instruction::Add => Some(Operator::Plus),
+            Instruction::Sub | Instruction::Neg => Some(Operator::Minus),
+            Instruction::Mul => Some(Operator::Star),
+            Instruction::Div => Some(Operator::Slash),
+            Instruction::Pow => Some(Operator::Caret),
+            Instruction::CallN(operator, _) => Some(operator.clone()),
+        }
+    }
+}
+
+impl Program {
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Run the program on a new [`Machine`]. See [`Machine::run`].
+    pub fn evaluate(&self, values: &[f64]) -> Result<f64, EvalError> {
+        Machine::new().run(self, values)
+    }
+}
+
+impl Machine {
+    pub fn new() -> Machine {
+        Machine::default()
+    }
+
+    /// Run a program. `values` must contain the value of each variable, in
+    /// the order used to lower the expression.
+    ///
+    /// ## Errors:
+    /// The same as [`CompiledExpression::evaluate`](crate::CompiledExpression::evaluate),
+    /// or [`EvalError::InvalidProgram`] if the program was not produced by
+    /// [`ArithmeticExpression::to_program`] and is not valid.
+    pub fn run(&mut self, program: &Program, values: &[f64]) -> Result<f64, EvalError> {
+        if values.len() != program.variables {
+            return Err(EvalError::WrongValueCount {
+                expected: program.variables,
+                provided: values.len(),
+            });
+        }
+        // Every instruction pushes one value, so a valid program never needs
+        // more values than instructions (a deserialized one can claim to)
+        if program.max_stack > program.instructions.len() {
+            return Err(EvalError::InvalidProgram {
+                position: program.instructions.len(),
+            });
+        }
+        let stack = &mut self.stack;
+        stack.clear();
+        stack.reserve(program.max_stack);
+
+        for (position, instruction) in program.instructions.iter().enumerate() {
+            let invalid = || EvalError::InvalidProgram { position };
+            let arguments = match instruction {
+                Instruction::PushConst(_) | Instruction::PushVar(_) => 0,
+                Instruction::Neg => 1,
+                Instruction::CallN(_, arguments) => *arguments,
+                _ => 2,
+            };
+            if stack.len() < arguments {
+                return Err(invalid());
+            }
+            let args_start = stack.len() - arguments;
+            let args = &stack[args_start..];
+
+            let result = match (instruction, args) {
+                (Instruction::PushConst(n), _) => *n,
+                (Instruction::PushVar(i), _) => *values.get(*i).ok_or_else(invalid)?,
+                (Instruction::Add, [x, y]) => x + y,
+                (Instruction::Sub, [x, y]) => x - y,
+                (Instruction::Mul, [x, y]) => x * y,
+                (Instruction::Div, [x, y]) => x / y,
+                (Instruction::Pow, [x, y]) => x.powf(*y),
+                (Instruction::Neg, [x]) => -x,
+                (Instruction::CallN(operator, _), _) => operator.apply(args)?,
+                _ => return Err(invalid()),
+            };
+            if !result.is_finite() {
+                if let Some(operator) = instruction.operator() {
+                    program.arithmetic.check(&operator, args, result)?;
+                }
+            }
+            stack.truncate(args_start);
+            stack.push(result);
+        }
+
+        match stack.as_slice() {
+            [result] => Ok(*result),
+            _ => Err(EvalError::InvalidProgram {
+                position: program.instructions.len(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::corpus::CORPUS;
+    use crate::{Arity, Context};
+
+    const VARIABLES: [&str; 7] = ["x", "y", "àć", "ü", "yy", "xz", "z"];
+
+    #[test]
+    fn test_program_corpus() {
+        let values = [
+            [4_f64, 1_f64, 4_f64, 0.5, 1_f64, 4_f64, 0_f64],
+            [-3_f64, 2.5, 1e-300, -1e300, 0_f64, 3_f64, 1_f64],
+        ];
+        let mut machine = Machine::new();
+        for (input, _, _) in CORPUS.iter() {
+            let expression = ArithmeticExpression::parse(input).unwrap();
+            let program = expression.to_program(&VARIABLES).unwrap();
+            for values in values.iter() {
+                let variables: Vec<_> = VARIABLES
+                    .iter()
+                    .cloned()
+                    .zip(values.iter().cloned())
+                    .collect();
+                let expected = expression.evaluate(&variables[..]).unwrap();
+                let result = machine.run(&program, values).unwrap();
+                assert_eq!(expected.to_bits(), result.to_bits(), "{}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_program_instructions() {
+        let program = ArithmeticExpression::parse("-x * (2 + max(y, 3, pi))")
+            .unwrap()
+            .to_program(&["x", "y"])
+            .unwrap();
+        assert_eq!(
+            program.instructions(),
+            &[
+                Instruction::PushVar(0),
+                Instruction::Neg,
+                Instruction::PushConst(2_f64),
+                Instruction::PushVar(1),
+                Instruction::PushConst(3_f64),
+                Instruction::PushConst(std::f64::consts::PI),
+                Instruction::CallN(Operator::Max, 3),
+                Instruction::Add,
+                Instruction::Mul,
+            ]
+        );
+        assert_eq!(program.max_stack, 5);
+    }
+
+    #[test]
+    fn test_deep_program() {
+        let mut expression = ArithmeticExpression::VariableLeaf("x".to_string());
+        for _ in 0..10000 {
+            expression = ArithmeticExpression::Node {
+                node: Operator::Minus,
+                operands: vec![expression],
+            };
+        }
+        let program = expression.to_program(&["x"]).unwrap();
+        assert_eq!(program.evaluate(&[3_f64]), Ok(3_f64));
+    }
+
+    #[test]
+    fn test_program_errors() {
+        let mut context = Context::new();
+        context.register_function("f", Arity::Exactly(1), |args: &[f64]| {
+            if args[0] < 0.0 {
+                Err("negative")
+            } else {
+                Ok(args[0])
+            }
+        });
+        let expression = ArithmeticExpression::parse_with_context("f(x) / y", &context).unwrap();
+        assert_eq!(
+            expression.to_program(&["x"]).unwrap_err(),
+            EvalError::MissingVariables {
+                names: vec!["y".to_string()]
+            }
+        );
+
+        let options = EvaluationOptions {
+            arithmetic: ArithmeticMode::Checked,
+            ..EvaluationOptions::default()
+        };
+        let program = expression.to_program_with(&["x", "y"], &options).unwrap();
+        let mut machine = Machine::new();
+        assert_eq!(machine.run(&program, &[1_f64, 2_f64]), Ok(0.5));
+        assert_eq!(
+            machine.run(&program, &[-1_f64, 2_f64]),
+            Err(EvalError::FunctionFailed {
+                name: "f".to_string(),
+                message: "negative".to_string()
+            })
+        );
+        assert_eq!(
+            machine.run(&program, &[1_f64, 0_f64]),
+            Err(EvalError::DivisionByZero {
+                operator: Operator::Slash
+            })
+        );
+        assert_eq!(
+            machine.run(&program, &[1_f64]),
+            Err(EvalError::WrongValueCount {
+                expected: 2,
+                provided: 1
+            })
+        );
+
+        let invalid = |instructions| Program {
+            instructions,
+            variables: 1,
+            max_stack: 0,
+            arithmetic: ArithmeticMode::Ieee,
+        };
+        let program = invalid(vec![Instruction::PushVar(0), Instruction::Add]);
+        assert_eq!(
+            program.evaluate(&[1_f64]),
+            Err(EvalError::InvalidProgram { position: 1 })
+        );
+        let program = invalid(vec![Instruction::PushVar(1)]);
+        assert_eq!(
+            program.evaluate(&[1_f64]),
+            Err(EvalError::InvalidProgram { position: 0 })
+        );
+        let program = invalid(vec![Instruction::PushVar(0), Instruction::PushVar(0)]);
+        assert_eq!(
+            program.evaluate(&[1_f64]),
+            Err(EvalError::InvalidProgram { position: 2 })
+        );
+        let program = invalid(vec![
+            Instruction::PushVar(0),
+            Instruction::CallN(Operator::Sqrt, 2),
+        ]);
+        assert_eq!(
+            program.evaluate(&[1_f64]),
+            Err(EvalError::InvalidProgram { position: 1 })
+        );
+        let program = Program {
+            max_stack: 1 << 60,
+            ..invalid(vec![Instruction::PushVar(0)])
+        };
+        assert_eq!(
+            program.evaluate(&[1_f64]),
+            Err(EvalError::InvalidProgram { position: 1 })
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialized_program() {
+        let program = ArithmeticExpression::parse("x * 2")
+            .unwrap()
+            .to_program(&["x"])
+            .unwrap();
+        let json = serde_json::to_string(&program).unwrap();
+        let decoded: Program = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.evaluate(&[3_f64]), Ok(6_f64));
+
+        let json = json.replace("\"max_stack\":2", "\"max_stack\":1152921504606846976");
+        let decoded: Program = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            decoded.evaluate(&[3_f64]),
+            Err(EvalError::InvalidProgram { position: 3 })
+        );
+    }
+}
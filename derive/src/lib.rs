@@ -0,0 +1,444 @@
+//! Procedural macros of `arithmetic_parser`:
+//! - `#[derive(Variables)]`, which lets a struct provide the values of the
+//!   variables of an `arithmetic_parser::ArithmeticExpression`.
+//! - `expr!`, which parses an expression at compile time.
+//!
+//! Use them through the `derive` feature of `arithmetic_parser`, which
+//! re-exports the macros.
+
+#![deny(rust_2018_idioms)]
+
+use arithmetic_parser_core::{ArithmeticExpression, Operator};
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::ext::IdentExt;
+use syn::spanned::Spanned;
+use syn::{Data, DeriveInput, Error, Fields, LitStr};
+
+/// Implement `VariableResolver` for a struct with named fields: each field
+/// is a variable with the same name, whose value is the value of the field.
+///
+/// The type of every field must implement `VariableValue`, which is the case
+/// for `f64`, the numeric types which convert to `f64` without loss and
+/// `Option`s of them (`None` is a missing variable). Fields can be
+/// configured with the `var` attribute:
+/// - `#[var(rename = "name")]` uses a different name for the variable.
+/// - `#[var(skip)]` ignores the field, which can then have any type.
+///
+/// ## Example:
+/// ```
+/// use arithmetic_parser::{ArithmeticExpression, Variables};
+///
+/// #[derive(Variables)]
+/// struct Trade {
+///     price: f64,
+///     #[var(rename = "quantity")]
+///     qty: u32,
+///     fee: Option<f32>,
+///     #[var(skip)]
+///     id: String,
+/// }
+///
+/// let trade = Trade { price: 2.5, qty: 4, fee: Some(0.5), id: "t1".to_string() };
+/// let expression = ArithmeticExpression::parse("price * quantity + fee").unwrap();
+/// assert_eq!(10.5, expression.evaluate(&trade).unwrap());
+/// ```
+///
+/// Fields of other types are reported at compile time:
+/// ```compile_fail
+/// use arithmetic_parser::Variables;
+///
+/// #[derive(Variables)]
+/// struct Trade {
+///     price: f64,
+///     id: String,
+/// }
+/// ```
+///
+/// So are names which the parser does not read as variables, such as
+/// constants, numbers or names with spaces:
+/// ```compile_fail
+/// use arithmetic_parser::Variables;
+///
+/// #[derive(Variables)]
+/// struct Circle {
+///     #[var(rename = "pi")]
+///     ratio: f64,
+/// }
+/// ```
+/// ```compile_fail
+/// use arithmetic_parser::Variables;
+///
+/// #[derive(Variables)]
+/// struct Trade {
+///     #[var(rename = "unit price")]
+///     price: f64,
+/// }
+/// ```
+#[proc_macro_derive(Variables, attributes(var))]
+pub fn derive_variables(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Parse an arithmetic expression at compile time.
+///
+/// The string literal is parsed like `ArithmeticExpression::parse` does,
+/// and the macro expands to the code which builds the resulting
+/// `ArithmeticExpression`, so no parsing happens at run time. An invalid
+/// expression is a compile error, which shows where the input is wrong.
+///
+/// Custom functions cannot be used, since they are registered at run time
+/// in a `Context`.
+///
+/// ## Example:
+/// ```
+/// use arithmetic_parser::{expr, ArithmeticExpression};
+///
+/// let expression = expr!("(x+y)/(x-y)");
+/// assert_eq!(expression, ArithmeticExpression::parse("(x+y)/(x-y)").unwrap());
+/// assert_eq!(1.5, expression.evaluate(&[("x", 5_f64), ("y", 1_f64)]).unwrap());
+/// ```
+///
+/// Syntax errors are reported at compile time:
+/// ```compile_fail
+/// use arithmetic_parser::expr;
+///
+/// let expression = expr!("2 + (x*4");
+/// ```
+///
+/// So are wrong numbers of arguments:
+/// ```compile_fail
+/// use arithmetic_parser::expr;
+///
+/// let expression = expr!("atan2(y) + 1");
+/// ```
+///
+/// The input must be a string literal:
+/// ```compile_fail
+/// use arithmetic_parser::expr;
+///
+/// let expression = expr!(x + 1);
+/// ```
+#[proc_macro]
+pub fn expr(input: TokenStream) -> TokenStream {
+    let literal = syn::parse_macro_input!(input as LitStr);
+    expand_expr(&literal).into()
+}
+
+fn expand_expr(literal: &LitStr) -> TokenStream2 {
+    let source = literal.value();
+    match ArithmeticExpression::parse(&source) {
+        Ok(expression) => expression_tokens(&expression),
+        Err(error) => {
+            // The message shows where the input is wrong, since stable
+            // compilers cannot point inside the literal
+            let diagnostic = error.diagnostic(&source).to_string();
+            let message = diagnostic.trim_start_matches("error: ").trim_end();
+            Error::new(literal.span(), message).into_compile_error()
+        }
+    }
+}
+
+/// The code which builds `expression`.
+fn expression_tokens(expression: &ArithmeticExpression) -> TokenStream2 {
+    match expression {
+        ArithmeticExpression::NumberLeaf(n) => {
+            // The bits keep `inf`, `nan` and `-0` exact
+            let bits = n.to_bits();
+            quote! {
+                ::arithmetic_parser::ArithmeticExpression::NumberLeaf(f64::from_bits(#bits))
+            }
+        }
+        ArithmeticExpression::VariableLeaf(x) => quote! {
+            ::arithmetic_parser::ArithmeticExpression::VariableLeaf(::std::string::String::from(#x))
+        },
+        ArithmeticExpression::Constant { name, value } => {
+            let bits = value.to_bits();
+            quote! {
+                ::arithmetic_parser::ArithmeticExpression::Constant {
+                    name: ::std::string::String::from(#name),
+                    value: f64::from_bits(#bits),
+                }
+            }
+        }
+        ArithmeticExpression::Node { node, operands } => {
+            let node = operator_variant(node);
+            let operands = operands.iter().map(expression_tokens);
+            quote! {
+                ::arithmetic_parser::ArithmeticExpression::Node {
+                    node: ::arithmetic_parser::Operator::#node,
+                    operands: ::std::vec![#(#operands),*],
+                }
+            }
+        }
+    }
+}
+
+/// The name of the variant of `operator`.
+fn operator_variant(operator: &Operator) -> TokenStream2 {
+    match operator {
+        Operator::Plus => quote!(Plus),
+        Operator::Minus => quote!(Minus),
+        Operator::Star => quote!(Star),
+        Operator::Slash => quote!(Slash),
+        Operator::Caret => quote!(Caret),
+        Operator::Sqrt => quote!(Sqrt),
+        Operator::Sin => quote!(Sin),
+        Operator::Cos => quote!(Cos),
+        Operator::Tan => quote!(Tan),
+        Operator::Asin => quote!(Asin),
+        Operator::Acos => quote!(Acos),
+        Operator::Atan => quote!(Atan),
+        Operator::Atan2 => quote!(Atan2),
+        Operator::Sinh => quote!(Sinh),
+        Operator::Cosh => quote!(Cosh),
+        Operator::Tanh => quote!(Tanh),
+        Operator::Exp => quote!(Exp),
+        Operator::Ln => quote!(Ln),
+        Operator::Log10 => quote!(Log10),
+        Operator::Log2 => quote!(Log2),
+        Operator::Log => quote!(Log),
+        Operator::Pow => quote!(Pow),
+        Operator::Abs => quote!(Abs),
+        Operator::Floor => quote!(Floor),
+        Operator::Ceil => quote!(Ceil),
+        Operator::Round => quote!(Round),
+        Operator::Trunc => quote!(Trunc),
+        Operator::Sign => quote!(Sign),
+        Operator::Min => quote!(Min),
+        Operator::Max => quote!(Max),
+        Operator::Hypot => quote!(Hypot),
+        Operator::Custom(_) => unreachable!("custom functions are not parsed"),
+    }
+}
+
+/// The options of a field, from its `var` attributes.
+#[derive(Default)]
+struct FieldOptions {
+    rename: Option<LitStr>,
+    skip: bool,
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    data.fields.span(),
+                    "`Variables` can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.ident.span(),
+                "`Variables` can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut names: Vec<String> = Vec::new();
+    let mut arms = Vec::new();
+    for field in fields {
+        let options = field_options(field)?;
+        if options.skip {
+            continue;
+        }
+        let ident = field.ident.as_ref().unwrap();
+        let name = match &options.rename {
+            Some(rename) => rename.value(),
+            None => ident.unraw().to_string(),
+        };
+        let span = options.rename.as_ref().map_or(ident.span(), LitStr::span);
+        check_name(&name, span)?;
+        if names.contains(&name) {
+            return Err(Error::new(
+                span,
+                format!("more than one field is the variable `{}`", name),
+            ));
+        }
+        // The span of the type makes the compiler point at it if it does
+        // not implement `VariableValue`
+        let value = quote_spanned! {field.ty.span()=>
+            ::arithmetic_parser::VariableValue::value(&self.#ident)
+        };
+        arms.push(quote! { #name => #value, });
+        names.push(name);
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::arithmetic_parser::VariableResolver for #ident #ty_generics
+        #where_clause
+        {
+            fn resolve(&self, name: &str) -> ::std::option::Option<f64> {
+                match name {
+                    #(#arms)*
+                    _ => ::std::option::Option::None,
+                }
+            }
+        }
+    })
+}
+
+/// Check that the parser reads `name` as a variable.
+fn check_name(name: &str, span: proc_macro2::Span) -> Result<(), Error> {
+    match ArithmeticExpression::parse(name) {
+        Ok(ArithmeticExpression::VariableLeaf(variable)) if variable == name => Ok(()),
+        Ok(ArithmeticExpression::Constant { .. }) => Err(Error::new(
+            span,
+            format!(
+                "`{}` is a constant, not a variable: use `#[var(rename = \"...\")]` or `#[var(skip)]`",
+                name
+            ),
+        )),
+        _ => Err(Error::new(
+            span,
+            format!("`{}` is not a valid variable name", name),
+        )),
+    }
+}
+
+fn field_options(field: &syn::Field) -> Result<FieldOptions, Error> {
+    let mut options = FieldOptions::default();
+    for attribute in field.attrs.iter().filter(|a| a.path().is_ident("var")) {
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                options.rename = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("skip") {
+                options.skip = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `rename = \"...\"` or `skip`"))
+            }
+        })?;
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(input: &str) -> String {
+        let input = syn::parse_str(input).unwrap();
+        expand(&input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_expand() {
+        let input = syn::parse_str(
+            r#"struct Point<T> where T: Copy {
+                x: f64,
+                #[var(rename = "height")]
+                r#y: T,
+                #[var(skip)]
+                label: String,
+            }"#,
+        )
+        .unwrap();
+        let expanded = expand(&input).unwrap().to_string();
+        assert!(expanded.contains(
+            "impl < T > :: arithmetic_parser :: VariableResolver for Point < T > where T : Copy"
+        ));
+        assert!(expanded
+            .contains("\"x\" => :: arithmetic_parser :: VariableValue :: value (& self . x)"));
+        assert!(expanded.contains(
+            "\"height\" => :: arithmetic_parser :: VariableValue :: value (& self . r#y)"
+        ));
+        assert!(!expanded.contains("label"));
+    }
+
+    fn expand_expr_str(input: &str) -> String {
+        expand_expr(&syn::parse_str(input).unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_expand_expr() {
+        let bits = |n: f64| format!("f64 :: from_bits ({}u64)", n.to_bits());
+        assert_eq!(
+            expand_expr_str(r#""x + 2""#),
+            format!(
+                ":: arithmetic_parser :: ArithmeticExpression :: Node {{ \
+                 node : :: arithmetic_parser :: Operator :: Plus , \
+                 operands : :: std :: vec ! [\
+                 :: arithmetic_parser :: ArithmeticExpression :: VariableLeaf \
+                 (:: std :: string :: String :: from (\"x\")) , \
+                 :: arithmetic_parser :: ArithmeticExpression :: NumberLeaf ({})\
+                 ] , }}",
+                bits(2.0)
+            )
+        );
+        assert_eq!(
+            expand_expr_str(r#""pi""#),
+            format!(
+                ":: arithmetic_parser :: ArithmeticExpression :: Constant {{ \
+                 name : :: std :: string :: String :: from (\"pi\") , \
+                 value : {} , }}",
+                bits(std::f64::consts::PI)
+            )
+        );
+
+        let expanded = expand_expr_str(r#""max(sqrt x, -y) ^ 2 / atan2(1, 0)""#);
+        for operator in ["Max", "Sqrt", "Minus", "Caret", "Slash", "Atan2"].iter() {
+            let variant = format!(":: arithmetic_parser :: Operator :: {} ,", operator);
+            assert!(expanded.contains(&variant), "{}", operator);
+        }
+    }
+
+    #[test]
+    fn test_expand_expr_errors() {
+        let expanded = expand_expr_str(r#""2 + (x*4""#);
+        assert!(expanded.starts_with(":: core :: compile_error !"));
+        assert!(expanded.contains("Parenthesis at pos 4 is not balanced"));
+        assert!(expanded.contains("did you forget a closing `)`?"));
+
+        // Custom functions are not known at compile time
+        let expanded = expand_expr_str(r#""f(x)""#);
+        assert!(expanded.starts_with(":: core :: compile_error !"));
+    }
+
+    #[test]
+    fn test_expand_errors() {
+        assert_eq!(
+            error("struct Point(f64, f64);"),
+            "`Variables` can only be derived for structs with named fields"
+        );
+        assert_eq!(
+            error("enum Point { A }"),
+            "`Variables` can only be derived for structs"
+        );
+        assert_eq!(
+            error(r#"struct Point { x: f64, #[var(rename = "x")] y: f64 }"#),
+            "more than one field is the variable `x`"
+        );
+        assert_eq!(
+            error("struct Point { #[var(name = 1)] x: f64 }"),
+            "expected `rename = \"...\"` or `skip`"
+        );
+        assert_eq!(
+            error(r#"struct Circle { #[var(rename = "pi")] p: f64 }"#),
+            "`pi` is a constant, not a variable: use `#[var(rename = \"...\")]` or `#[var(skip)]`"
+        );
+        assert_eq!(
+            error("struct Circle { e: f64 }"),
+            "`e` is a constant, not a variable: use `#[var(rename = \"...\")]` or `#[var(skip)]`"
+        );
+        for name in ["a b", "1e5", "sqrt", "x+y", ""].iter() {
+            assert_eq!(
+                error(&format!(
+                    r#"struct Point {{ #[var(rename = "{}")] x: f64 }}"#,
+                    name
+                )),
+                format!("`{}` is not a valid variable name", name)
+            );
+        }
+    }
+}
@@ -12,11 +12,12 @@ fn main() {
         let expression = match parser::ArithmeticExpression::parse(&line) {
             Ok(e) => e,
             Err(e) => {
-                println!("Error: {}", e);
+                print!("{}", e.diagnostic(line.trim_end()));
                 continue;
             }
         };
 
+        let source = line.trim_end().to_string();
         println!(
             "Now enter list of space separated variable values (e.g., x 2 y 1). CTRL-C to quit."
         );
@@ -35,7 +36,7 @@ fn main() {
 
         match expression.evaluate(&variables) {
             Ok(r) => println!("Result is: {}", r),
-            Err(e) => println!("Error: {}", e),
+            Err(e) => print!("{}", e.diagnostic(&source)),
         }
     }
 }
@@ -25,12 +25,7 @@ impl Wrapper {
     }
 
     fn evaluate(&self) -> f64 {
-        let variables_ref = self
-            .variables
-            .iter()
-            .map(|(x, y)| (x.as_ref(), *y))
-            .collect();
-        self.expression.evaluate(&variables_ref).unwrap()
+        self.expression.evaluate(&self.variables).unwrap()
     }
 }
 